@@ -0,0 +1,72 @@
+use poem::{
+    get, handler, http::{HeaderMap, StatusCode}, listener::TcpListener, post,
+    web::{Data, Json, Query}, EndpointExt, Route, Server
+};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::emergency;
+use crate::settings;
+use crate::status::{self, IntegrationStatuses};
+
+// A web page can only send a custom header after a CORS preflight, which this server never answers
+const CALLER_HEADER: &str = "X-QTHub-Caller";
+
+
+#[derive(Deserialize)]
+struct StopParams {
+    notify_devices: Option<bool>
+}
+
+#[derive(Serialize)]
+struct ApiStatus {
+    emergency_stop: bool,
+    integrations: IntegrationStatuses
+}
+
+fn api_status(app: &AppHandle) -> Json<ApiStatus> {
+    Json(ApiStatus { emergency_stop: emergency::is_active(), integrations: status::all(app) })
+}
+
+// Keeps other websites open in the browser from posting to the API
+fn check_caller(headers: &HeaderMap) -> poem::Result<()> {
+    if headers.contains_key(CALLER_HEADER) {
+        return Ok(());
+    }
+    Err(poem::Error::from_string(format!("Requests need the {} header", CALLER_HEADER), StatusCode::FORBIDDEN))
+}
+
+#[handler]
+async fn panic_stop(app: Data<&AppHandle>, headers: &HeaderMap, Query(params): Query<StopParams>) -> poem::Result<Json<ApiStatus>> {
+    check_caller(headers)?;
+    emergency::engage(&app, "HTTP API", params.notify_devices.unwrap_or(true)).await;
+    Ok(api_status(&app))
+}
+
+#[handler]
+async fn rearm(app: Data<&AppHandle>, headers: &HeaderMap) -> poem::Result<Json<ApiStatus>> {
+    check_caller(headers)?;
+    emergency::rearm(&app, "HTTP API");
+    Ok(api_status(&app))
+}
+
+#[handler]
+async fn get_status(app: Data<&AppHandle>) -> Json<ApiStatus> {
+    api_status(&app)
+}
+
+pub async fn api_thread(app: AppHandle) {
+    let routes = Route::new()
+        .at("/panic", post(panic_stop))
+        .at("/rearm", post(rearm))
+        .at("/status", get(get_status))
+        .data(app);
+    // Local-only so external tools (stream decks, scripts) can reach QTHub but nothing else can
+    let api_addr = format!("127.0.0.1:{}", settings::ports().api);
+    match Server::new(TcpListener::bind(api_addr.as_str())).run(routes).await {
+        Ok(()) => {},
+        Err(e) => {
+            println!("Local API stopped, couldn't listen on {}: {}", api_addr, e);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use crate::client::{MAX_STRENGTH, MIN_STRENGTH};
+use crate::defines::{QTSAction, QTSInteraction};
+use crate::mappings::OscMapping;
+
+fn default_enabled() -> bool {
+    true
+}
+
+/*
+    Settings for one avatar, keyed by the avatar ID VRChat sends on /avatar/change.
+    `mappings` limits which OSC mappings are used while it's worn, None uses all of them.
+    The strength caps apply to everything it triggers, on top of the safety limits.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AvatarProfile {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub mappings: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_shock_strength: Option<u8>,
+    #[serde(default)]
+    pub max_vibrate_strength: Option<u8>
+}
+
+impl AvatarProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.id.starts_with("avtr_") {
+            return Err(format!("'{}' isn't a VRChat avatar ID", self.id));
+        }
+        for strength in [self.max_shock_strength, self.max_vibrate_strength].into_iter().flatten() {
+            if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+                return Err(format!("Avatar strength caps must be between {} and {}", MIN_STRENGTH, MAX_STRENGTH));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn uses_mapping(&self, mapping: &OscMapping) -> bool {
+        match &self.mappings {
+            Some(names) => names.iter().any(|name| name.eq_ignore_ascii_case(&mapping.name)),
+            None => true
+        }
+    }
+
+    pub fn caps(&self) -> StrengthCaps {
+        StrengthCaps { shock: self.max_shock_strength, vibrate: self.max_vibrate_strength }
+    }
+}
+
+/*
+    The worn avatar's strength caps. They're applied per shocker in trigger_qtshock, to the
+    requested strength or the shocker's own default, so they can only ever lower a strength.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StrengthCaps {
+    pub shock: Option<u8>,
+    pub vibrate: Option<u8>
+}
+
+impl StrengthCaps {
+    pub fn get(&self, interaction: QTSInteraction) -> Option<u8> {
+        match interaction {
+            QTSInteraction::SHOCK => self.shock,
+            QTSInteraction::VIBRATE => self.vibrate,
+            QTSInteraction::BEEP => None
+        }
+    }
+
+    // Patterns mix interactions, their steps are capped one by one
+    pub fn for_action(&self, action: &QTSAction) -> Option<u8> {
+        match action {
+            QTSAction::Interaction(interaction) => self.get(*interaction),
+            QTSAction::Pattern(_) => None
+        }
+    }
+}
+
+pub enum AvatarGate {
+    // No profiles are set up, every avatar is allowed
+    Open,
+    Allowed(AvatarProfile),
+    Blocked(String)
+}
+
+/*
+    Once any profile exists only avatars with an enabled profile can trigger anything.
+    Until VRChat reports an avatar change we can't know what's worn, so nothing fires.
+*/
+pub fn gate(profiles: &[AvatarProfile], avatar: Option<&str>) -> AvatarGate {
+    if profiles.is_empty() {
+        return AvatarGate::Open;
+    }
+    let avatar = match avatar {
+        Some(avatar) => avatar,
+        None => {
+            return AvatarGate::Blocked("Waiting for VRChat to report the worn avatar, change avatar once to start".to_string());
+        }
+    };
+    match profiles.iter().find(|profile| profile.id == avatar) {
+        Some(profile) if profile.enabled => AvatarGate::Allowed(profile.clone()),
+        Some(_) => AvatarGate::Blocked(format!("Avatar {} is disabled", avatar)),
+        None => AvatarGate::Blocked(format!("Avatar {} isn't on the allow-list", avatar))
+    }
+}
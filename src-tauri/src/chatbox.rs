@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::client::DeviceError;
+use crate::defines::{QTSAction, QTSInteraction};
+use crate::safety::{SafetyVerdict, QTSHOCK_SAFETY};
+
+// VRChat cuts chatbox messages off here
+const MAX_MESSAGE_CHARS: usize = 144;
+// VRChat starts ignoring the chatbox when it's written to more often than this
+const MIN_INTERVAL_MS: u32 = 1500;
+
+/*
+    Messages shown in the VRChat chatbox when an OSC trigger fires. Templates can use
+    {strength}, {target} and {pattern}, the cooldown one also {seconds} and {reason}.
+    An empty template sends nothing for that case.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatboxConfig {
+    pub enabled: bool,
+    pub shock: String,
+    pub vibrate: String,
+    pub beep: String,
+    pub pattern: String,
+    pub cooldown: String,
+    pub interval_ms: u32,
+    // Play VRChat's notification sound with each message
+    pub sound: bool
+}
+
+impl Default for ChatboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shock: "Zapped at {strength}%!".to_string(),
+            vibrate: "Buzzed at {strength}%".to_string(),
+            beep: "Beep!".to_string(),
+            pattern: "Playing {pattern}".to_string(),
+            cooldown: "Cooldown {seconds}s".to_string(),
+            interval_ms: MIN_INTERVAL_MS,
+            sound: false
+        }
+    }
+}
+
+impl ChatboxConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_ms < MIN_INTERVAL_MS {
+            return Err(format!("Chatbox messages must be at least {}ms apart", MIN_INTERVAL_MS));
+        }
+        Ok(())
+    }
+
+    fn cooldown_message(&self, reason: &str) -> Option<String> {
+        let seconds = QTSHOCK_SAFETY.lock().unwrap().cooldown_remaining()?.as_secs_f32().ceil();
+        non_empty(self.cooldown.replace("{seconds}", &seconds.to_string()).replace("{reason}", reason))
+    }
+
+    /*
+        Picks and fills the template for a trigger's outcome. `custom` is a mapping's own
+        template and replaces whichever one would have been used, except for cooldowns.
+        A shock the rate limits rejected or turned into something milder counts as a cooldown.
+    */
+    pub fn message(&self, result: &Result<Option<SafetyVerdict>, DeviceError>, action: &QTSAction, target: &str, custom: Option<&str>) -> Option<String> {
+        let (template, strength) = match result {
+            Err(DeviceError::Blocked(reason)) | Ok(Some(SafetyVerdict { rate_limit: Some(reason), .. })) => {
+                return self.cooldown_message(reason);
+            },
+            Err(_) => {
+                return None;
+            },
+            Ok(Some(verdict)) => {
+                let template = match verdict.interaction {
+                    QTSInteraction::SHOCK => &self.shock,
+                    QTSInteraction::VIBRATE => &self.vibrate,
+                    QTSInteraction::BEEP => &self.beep
+                };
+                (template, verdict.strength)
+            },
+            Ok(None) => (&self.pattern, 0)
+        };
+        let pattern = match action {
+            QTSAction::Pattern(name) => name.as_str(),
+            QTSAction::Interaction(_) => ""
+        };
+        let message = custom.unwrap_or(template)
+            .replace("{strength}", &strength.to_string())
+            .replace("{target}", if target.is_empty() { "default" } else { target })
+            .replace("{pattern}", pattern);
+        non_empty(message)
+    }
+}
+
+fn non_empty(message: String) -> Option<String> {
+    if message.trim().is_empty() {
+        return None;
+    }
+    Some(message.chars().take(MAX_MESSAGE_CHARS).collect())
+}
+
+// Drops messages that come in faster than VRChat accepts them
+pub struct ChatboxLimiter {
+    last_sent: Mutex<Option<Instant>>
+}
+
+impl ChatboxLimiter {
+    pub fn new() -> Self {
+        Self { last_sent: Mutex::new(None) }
+    }
+
+    pub fn allow(&self, interval_ms: u32) -> bool {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        if let Some(last) = *last_sent {
+            if last.elapsed() < Duration::from_millis(interval_ms as u64) {
+                return false;
+            }
+        }
+        *last_sent = Some(Instant::now());
+        true
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(4);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_RETRIES: u8 = 2;
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+pub const MIN_STRENGTH: u8 = 1;
+pub const MAX_STRENGTH: u8 = 99;
+
+
+#[derive(Debug, Clone)]
+pub enum DeviceError {
+    NoDevice,
+    UnknownTarget(String),
+    UnknownPattern(String),
+    Blocked(String),
+    EmergencyStop,
+    InvalidStrength(String),
+    Connection(String),
+    Timeout,
+    Status(u16),
+    Request(String)
+}
+
+impl DeviceError {
+    // Only retry when the request never reached the device, otherwise a slow
+    // response would fire the same shock twice.
+    fn is_retryable(&self) -> bool {
+        matches!(self, DeviceError::Connection(_))
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::NoDevice => write!(f, "No QTShock device has been found on the network"),
+            DeviceError::UnknownTarget(target) => write!(f, "No shocker, device or group matches '{}'", target),
+            DeviceError::UnknownPattern(name) => write!(f, "No pattern named '{}'", name),
+            DeviceError::Blocked(reason) => write!(f, "Blocked by safety limits: {}", reason),
+            DeviceError::EmergencyStop => write!(f, "Emergency stop is active"),
+            DeviceError::InvalidStrength(strength) => write!(f, "Invalid strength '{}' (must be between {} and {})", strength, MIN_STRENGTH, MAX_STRENGTH),
+            DeviceError::Connection(err) => write!(f, "Couldn't connect to the QTShock: {}", err),
+            DeviceError::Timeout => write!(f, "The QTShock didn't respond in time"),
+            DeviceError::Status(code) => write!(f, "The QTShock rejected the request (HTTP {})", code),
+            DeviceError::Request(err) => write!(f, "Request to the QTShock failed: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl From<reqwest::Error> for DeviceError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return DeviceError::Timeout;
+        }
+        if err.is_connect() {
+            return DeviceError::Connection(err.to_string());
+        }
+        match err.status() {
+            Some(status) => DeviceError::Status(status.as_u16()),
+            None => DeviceError::Request(err.to_string())
+        }
+    }
+}
+
+impl From<DeviceError> for String {
+    fn from(err: DeviceError) -> Self {
+        err.to_string()
+    }
+}
+
+
+pub fn parse_strength(strength: &str) -> Result<u8, DeviceError> {
+    match strength.trim().parse::<u8>() {
+        Ok(value) if (MIN_STRENGTH..=MAX_STRENGTH).contains(&value) => Ok(value),
+        _ => Err(DeviceError::InvalidStrength(strength.to_string()))
+    }
+}
+
+pub fn check_strength(strength: u8) -> Result<u8, DeviceError> {
+    if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+        return Err(DeviceError::InvalidStrength(strength.to_string()));
+    }
+    Ok(strength)
+}
+
+// Without a duration the device falls back to its default short pulse
+fn push_duration(params: &mut Vec<(&str, String)>, duration_ms: Option<u32>) {
+    if let Some(duration) = duration_ms {
+        params.push(("duration", duration.to_string()));
+    }
+}
+
+
+pub struct QTShockClient {
+    http: reqwest::Client
+}
+
+impl QTShockClient {
+    pub fn new() -> Self {
+        let http = match reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .build() {
+                Ok(client) => client,
+                Err(err) => {
+                    println!("Failed to build QTShock HTTP client, using defaults: {}", err);
+                    reqwest::Client::new()
+                }
+            };
+        Self { http }
+    }
+
+    pub async fn shock(&self, ip: &str, shocker: u8, strength: u8, duration_ms: Option<u32>) -> Result<(), DeviceError> {
+        let strength = check_strength(strength)?;
+        let mut params = vec![("shocker", shocker.to_string()), ("strength", strength.to_string())];
+        push_duration(&mut params, duration_ms);
+        self.post(ip, "shock", &params).await
+    }
+
+    pub async fn vibrate(&self, ip: &str, shocker: u8, strength: u8, duration_ms: Option<u32>) -> Result<(), DeviceError> {
+        let strength = check_strength(strength)?;
+        let mut params = vec![("shocker", shocker.to_string()), ("strength", strength.to_string())];
+        push_duration(&mut params, duration_ms);
+        self.post(ip, "vibrate", &params).await
+    }
+
+    pub async fn beep(&self, ip: &str, shocker: u8, duration_ms: Option<u32>) -> Result<(), DeviceError> {
+        let mut params = vec![("shocker", shocker.to_string())];
+        push_duration(&mut params, duration_ms);
+        self.post(ip, "beep", &params).await
+    }
+
+    // Asks the device to stop whatever it's currently doing
+    pub async fn stop(&self, ip: &str) -> Result<(), DeviceError> {
+        self.post(ip, "stop", &[]).await
+    }
+
+    async fn post(&self, ip: &str, endpoint: &str, params: &[(&str, String)]) -> Result<(), DeviceError> {
+        if ip.is_empty() {
+            return Err(DeviceError::NoDevice);
+        }
+        let url = format!("http://{}/{}", ip, endpoint);
+        let mut attempt: u8 = 0;
+        loop {
+            let err = match self.http.post(&url).form(params).send().await {
+                Ok(res) => {
+                    let status = res.status();
+                    if status.is_success() {
+                        return Ok(());
+                    }
+                    DeviceError::Status(status.as_u16())
+                },
+                Err(e) => DeviceError::from(e)
+            };
+            if attempt >= MAX_RETRIES || !err.is_retryable() {
+                return Err(err);
+            }
+            attempt += 1;
+            println!("QTShock request to {} failed, retrying ({}/{}): {}", url, attempt, MAX_RETRIES, err);
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+impl Default for QTShockClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Shared so every command reuses the same connection pool.
+pub fn qtshock_client() -> &'static QTShockClient {
+    static CLIENT: OnceLock<QTShockClient> = OnceLock::new();
+    CLIENT.get_or_init(QTShockClient::new)
+}
@@ -0,0 +1,319 @@
+use poem::{
+    handler, listener::{Listener, TcpListener}, post,
+    web::{Data, Json}, EndpointExt, Route, Server
+};
+use rfd::FileDialog;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::defines::QTSAction;
+use crate::safety::SafetyVerdict;
+use crate::settings::{self, Rules};
+use crate::status::StatusTracker;
+use crate::{devices, emergency, gsi_cfg, patterns, Payload, TriggerScope};
+
+
+struct CsRuntime {
+    cancel: Option<CancellationToken>,
+    server: Option<JoinHandle<()>>
+}
+
+/*
+    The CS2 game state integration. Held in Tauri's managed state, the GSI server runs as a
+    task that shuts down gracefully once its cancellation token fires.
+*/
+pub struct CsService {
+    runtime: tokio::sync::Mutex<CsRuntime>,
+    death_count: Mutex<u16>,
+    rules: Mutex<Rules>,
+    pub status: StatusTracker
+}
+
+impl CsService {
+    pub fn new() -> Self {
+        Self {
+            runtime: tokio::sync::Mutex::new(CsRuntime { cancel: None, server: None }),
+            death_count: Mutex::new(0),
+            rules: Mutex::new(Rules::default()),
+            status: StatusTracker::new("cs")
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules.lock().unwrap().clone()
+    }
+
+    pub fn set_rules(&self, rules: Rules) {
+        *self.rules.lock().unwrap() = rules;
+    }
+
+    // Binds the port before spawning so a port in use is reported to the caller
+    pub async fn start(&self, app: &AppHandle) -> Result<(), String> {
+        let mut runtime = self.runtime.lock().await;
+        if let Some(cancel) = runtime.cancel.as_ref() {
+            if !cancel.is_cancelled() {
+                return Ok(());
+            }
+        }
+        // Wait for the previous server to let go of the port
+        if let Some(server) = runtime.server.take() {
+            let _ = server.await;
+        }
+
+        let gsi_addr = format!("127.0.0.1:{}", settings::ports().gsi);
+        let acceptor = match TcpListener::bind(gsi_addr.clone()).into_acceptor().await {
+            Ok(acceptor) => acceptor,
+            Err(e) => {
+                return Err(format!("Couldn't listen on {}: {}", gsi_addr, e));
+            }
+        };
+        let _ = tracing_subscriber::fmt::try_init();
+        *self.death_count.lock().unwrap() = 0;
+        self.status.started(app, gsi_addr.clone());
+
+        let cancel = CancellationToken::new();
+        let server_cancel = cancel.clone();
+        let server_app = app.clone();
+        let server = tauri::async_runtime::spawn(async move {
+            let gsi_webserver = Route::new().at("/", post(cs_update)).data(server_app.clone());
+            let shutdown = server_cancel.clone();
+            let status = &server_app.state::<CsService>().inner().status;
+            match Server::new_with_acceptor(acceptor)
+                .run_with_graceful_shutdown(gsi_webserver,
+                async move {
+                    shutdown.cancelled().await
+                },
+                Some(Duration::from_secs(5)))
+                .await {
+                    Ok(()) => {
+                        status.stopped(&server_app);
+                    },
+                    Err(e) => {
+                        println!("GSI server stopped: {}", e);
+                        status.failed(&server_app, format!("GSI server stopped: {}", e));
+                        let _ = server_app.emit_all("cs-rust-event", Payload { message: format!("The CS2 integration stopped unexpectedly: {}", e) });
+                    }
+                };
+            // Lets the next start through without a stop first
+            server_cancel.cancel();
+            let _ = server_app.emit_all("cs-rust-event", Payload { message: format!("Toggled CS2 integration OFF").into() });
+        });
+        runtime.cancel = Some(cancel);
+        runtime.server = Some(server);
+        println!("Listening for CS2 game state on {}", gsi_addr);
+        Ok(())
+    }
+
+    // The server finishes in the background, the next start waits for it
+    pub async fn stop(&self) {
+        let runtime = self.runtime.lock().await;
+        if let Some(cancel) = runtime.cancel.as_ref() {
+            cancel.cancel();
+        }
+    }
+}
+
+
+async fn death_check(app: &AppHandle, data: Json<gsi_cs2::Body>) {
+    let player = match data.player.as_ref() {
+        Some(plyr) => {
+            plyr
+        },
+        None => {
+            return;
+        }
+    };
+    let match_stats = match player.match_stats.as_ref() {
+        Some(p_stats) => {
+            p_stats
+        },
+        None => {
+            return;
+        }
+    };
+    let provider = match data.provider.as_ref() {
+        Some(p_provider) => {
+            p_provider
+        },
+        None => {
+            return;
+        }
+    };
+
+    if provider.steam_id != player.steam_id.clone().unwrap() {
+        return;
+    }
+
+    let cs = app.state::<CsService>();
+    let died = {
+        let mut death_count = cs.death_count.lock().unwrap();
+        if *death_count > match_stats.deaths {
+            *death_count = 0;
+        }
+        if *death_count < match_stats.deaths {
+            *death_count = match_stats.deaths;
+            true
+        } else {
+            false
+        }
+    };
+
+    if died {
+        cs.status.handled();
+        println!("Player died!");
+        let rules = cs.rules();
+        match crate::trigger_action(app, &rules.cs_target, &rules.cs_death_action, None, &TriggerScope::default()).await {
+            Ok(Some(SafetyVerdict { reason: Some(reason), .. })) => {
+                println!("{}", reason);
+            },
+            Ok(_) => {},
+            Err(e) => {
+                cs.status.error(app, e.to_string());
+                println!("Failed to shock on death: {}", e);
+            }
+        }
+    }
+}
+
+#[handler]
+async fn cs_update(app: Data<&AppHandle>, data: Json<gsi_cs2::Body>) {
+    app.state::<CsService>().status.packet(&app);
+    death_check(&app, data).await;
+}
+
+
+#[tauri::command]
+pub fn create_cs_config(app: AppHandle) {
+    let folder: Option<PathBuf> = FileDialog::new()
+        .set_directory("/")
+        .set_title("Select the Counter Strike 2 game directory")
+        .pick_folder();
+
+    let cs_path: PathBuf = match folder {
+        Some(path) => path,
+        None => {
+            app.emit_all("cs-rust-event", Payload { message: "Setup failed. You must select your CS2 game directory to set up QTShock integration.".to_string() });
+            return;
+        }
+    };
+
+    let path_str = match cs_path.to_str() {
+        Some(path) => path,
+        None => {
+            app.emit_all("cs-rust-event", Payload { message: "Setup failed. Something is wrong with the name of your selected directory.".to_string() });
+            return;
+        }
+    };
+
+    if !path_str.ends_with("Counter-Strike Global Offensive") {
+        app.emit_all("cs-rust-event", Payload { message: "Setup failed. This is not the path to CS2..".to_string() });
+        return;
+    }
+
+    let gsi_config: gsi_cfg::gsi_cfg = gsi_cfg::gsi_cfg {
+        uri: format!("http://127.0.0.1:{}", settings::ports().gsi),
+        timeout: "1.0".to_string(),
+        buffer: "0.0".to_string(),
+        throttle: "0.0".to_string(),
+        heartbeat: "60.0".to_string(),
+        auth: gsi_cfg::gsi_auth { token: "TOKEN".to_string() },
+        output: gsi_cfg::gsi_output { precision: "3".to_string(), precision_position: "1".to_string(), precision_vector: "3".to_string() },
+        data: gsi_cfg::gsi_data {
+            map_round_wins: "1".to_string(),
+            map: "1".to_string(),
+            player_id: "1".to_string(),
+            player_match_stats: "1".to_string(),
+            player_state: "1".to_string(),
+            player_weapons: "1".to_string(),
+            provider: "1".to_string(),
+            round: "1".to_string(),
+            allgrenades: "1".to_string(),
+            allplayers_id: "1".to_string(),
+            allplayers_match_stats: "1".to_string(),
+            allplayers_position: "1".to_string(),
+            allplayers_state: "1".to_string(),
+            allplayers_weapons: "1".to_string(),
+            bomb: "1".to_string(),
+            phase_countdowns: "1".to_string(),
+            player_position: "1".to_string()
+        }
+
+    };
+    let gsi_config_data = match vdf_serde::to_string(&gsi_config) {
+        Ok(data) => {
+            println!("{}", data);
+            data
+        },
+        Err(e) => {
+            app.emit_all("cs-rust-event", Payload { message: "Setup failed. Something went wrong when creating the gsi config.".to_string() });
+            println!("{}", e);
+            return;
+        }
+    };
+    fs::write(path_str.to_string() + "/game/csgo/cfg/gamestate_integration_qtshock.cfg", gsi_config_data);
+}
+
+pub async fn set_enabled(app: &AppHandle, start: bool) -> Result<(), String> {
+    if start && emergency::is_active() {
+        let message = "Emergency stop is active, re-arm before enabling the CS2 integration.".to_string();
+        let _ = app.emit_all("cs-rust-event", Payload { message: message.clone() });
+        return Err(message);
+    }
+    let cs = app.state::<CsService>();
+    if !start {
+        cs.stop().await;
+        settings::set_integration(None, Some(false));
+        return Ok(());
+    }
+    if let Err(e) = cs.start(app).await {
+        cs.status.failed(app, e.clone());
+        let _ = app.emit_all("cs-rust-event", Payload { message: format!("Something went wrong when starting the CS2 integration. {}", e) });
+        return Err(e);
+    }
+    settings::set_integration(None, Some(true));
+    let _ = crate::beep(app.clone(), String::new(), None).await;
+    let _ = app.emit_all("cs-rust-event", Payload { message: format!("Toggled CS2 integration ON").into() });
+    Ok(())
+}
+
+// Picks up a new GSI port, only called while the integration is on
+pub async fn restart(app: &AppHandle) -> Result<(), String> {
+    let cs = app.state::<CsService>();
+    cs.stop().await;
+    if let Err(e) = cs.start(app).await {
+        cs.status.failed(app, e.clone());
+        let _ = app.emit_all("cs-rust-event", Payload { message: format!("Couldn't restart the CS2 integration on the new port. {}", e) });
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn start_cs_listener(app: AppHandle, start: bool) -> Result<(), String> {
+    set_enabled(&app, start).await
+}
+
+#[tauri::command]
+pub fn set_cs_death_action(app: AppHandle, action: QTSAction) -> Result<(), String> {
+    if let QTSAction::Pattern(name) = &action {
+        if patterns::find_pattern(name).is_none() {
+            return Err(format!("No pattern named '{}'", name));
+        }
+    }
+    app.state::<CsService>().rules.lock().unwrap().cs_death_action = action;
+    settings::changed();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_cs_target(app: AppHandle, target: String) -> Result<(), String> {
+    devices::resolve_target(&target)?;
+    app.state::<CsService>().rules.lock().unwrap().cs_target = target;
+    settings::changed();
+    Ok(())
+}
@@ -1,9 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum QTSOSCType {
     PUSH,
-    HIT
+    HIT,
+    PROP
 }
 
 impl FromStr for QTSOSCType {
@@ -14,13 +16,14 @@ impl FromStr for QTSOSCType {
         match input {
             "PUSH" => Ok(QTSOSCType::PUSH),
             "HIT" => Ok(QTSOSCType::HIT),
+            "PROP" => Ok(QTSOSCType::PROP),
             _ => Err(())
         }
     }
 
 } // Stack Overflow goodness
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum QTSInteraction {
     SHOCK,
     VIBRATE,
@@ -40,4 +43,11 @@ impl FromStr for QTSInteraction {
         }
     }
 
-}
\ No newline at end of file
+}
+
+// What a trigger (OSC parameter, game event) does when it fires
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum QTSAction {
+    Interaction(QTSInteraction),
+    Pattern(String)
+}
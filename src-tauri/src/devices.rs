@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::client::{DeviceError, MAX_STRENGTH, MIN_STRENGTH};
+use crate::osc_address::{is_target_name, RESERVED_TARGETS};
+use crate::settings;
+
+pub const ALL_TARGET: &str = "all";
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Shocker {
+    pub index: u8,
+    pub name: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    // Used when an interaction doesn't ask for a specific strength
+    #[serde(default)]
+    pub shock_strength: Option<u8>,
+    #[serde(default)]
+    pub vibrate_strength: Option<u8>
+}
+
+impl Shocker {
+    pub fn new(index: u8, name: &str) -> Self {
+        Self { index, name: name.to_string(), groups: Vec::new(), shock_strength: None, vibrate_strength: None }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Device {
+    pub name: String,
+    pub ip: String,
+    pub shockers: Vec<Shocker>
+}
+
+impl Device {
+    pub fn new(name: &str, ip: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ip: ip.to_string(),
+            shockers: vec![Shocker::new(0, "Shocker 0")]
+        }
+    }
+
+    fn find_shocker(&self, shocker: &str) -> Option<&Shocker> {
+        match shocker.parse::<u8>() {
+            Ok(index) => self.shockers.iter().find(|s| s.index == index),
+            Err(_) => self.shockers.iter().find(|s| s.name.eq_ignore_ascii_case(shocker))
+        }
+    }
+}
+
+// A single resolved shocker on a single device
+#[derive(Clone, Debug)]
+pub struct ShockerTarget {
+    pub device: String,
+    pub ip: String,
+    pub shocker: u8,
+    pub shock_strength: Option<u8>,
+    pub vibrate_strength: Option<u8>
+}
+
+impl ShockerTarget {
+    fn new(device: &Device, shocker: &Shocker) -> Self {
+        Self {
+            device: device.name.clone(),
+            ip: device.ip.clone(),
+            shocker: shocker.index,
+            shock_strength: shocker.shock_strength,
+            vibrate_strength: shocker.vibrate_strength
+        }
+    }
+}
+
+pub static QTSHOCK_DEVICES: Mutex<Vec<Device>> = Mutex::new(Vec::new());
+
+
+/*
+    Targets are resolved in this order:
+        ""                  -> the first shocker of the first device
+        "all"               -> every shocker on every device
+        "<index>"           -> shocker <index> on the first device
+        "<device>/<shocker>" -> a shocker (by name or index) on a named device
+        "<device>"          -> every shocker on a named device
+        "<shocker>"         -> every shocker with that name
+        "<group>"           -> every shocker in that group
+*/
+pub fn resolve_target(target: &str) -> Result<Vec<ShockerTarget>, DeviceError> {
+    let devices = QTSHOCK_DEVICES.lock().unwrap();
+    let first_device = match devices.first() {
+        Some(device) => device,
+        None => {
+            return Err(DeviceError::NoDevice);
+        }
+    };
+    let target = target.trim();
+
+    if target.is_empty() {
+        return match first_device.shockers.first() {
+            Some(shocker) => Ok(vec![ShockerTarget::new(first_device, shocker)]),
+            None => Err(DeviceError::UnknownTarget(target.to_string()))
+        };
+    }
+
+    if target.eq_ignore_ascii_case(ALL_TARGET) {
+        return Ok(devices.iter()
+            .flat_map(|d| d.shockers.iter().map(move |s| ShockerTarget::new(d, s)))
+            .collect());
+    }
+
+    if let Ok(index) = target.parse::<u8>() {
+        // Unregistered indexes are still passed through so older setups keep working
+        return match first_device.shockers.iter().find(|s| s.index == index) {
+            Some(shocker) => Ok(vec![ShockerTarget::new(first_device, shocker)]),
+            None => Ok(vec![ShockerTarget::new(first_device, &Shocker::new(index, ""))])
+        };
+    }
+
+    if let Some((device_name, shocker_name)) = target.split_once('/') {
+        let device = match devices.iter().find(|d| d.name.eq_ignore_ascii_case(device_name)) {
+            Some(device) => device,
+            None => {
+                return Err(DeviceError::UnknownTarget(target.to_string()));
+            }
+        };
+        return match device.find_shocker(shocker_name) {
+            Some(shocker) => Ok(vec![ShockerTarget::new(device, shocker)]),
+            None => Err(DeviceError::UnknownTarget(target.to_string()))
+        };
+    }
+
+    if let Some(device) = devices.iter().find(|d| d.name.eq_ignore_ascii_case(target)) {
+        return Ok(device.shockers.iter().map(|s| ShockerTarget::new(device, s)).collect());
+    }
+
+    let matches: Vec<ShockerTarget> = devices.iter()
+        .flat_map(|d| d.shockers.iter()
+            .filter(|s| s.name.eq_ignore_ascii_case(target) || s.groups.iter().any(|g| g.eq_ignore_ascii_case(target)))
+            .map(move |s| ShockerTarget::new(d, s)))
+        .collect();
+
+    if matches.is_empty() {
+        return Err(DeviceError::UnknownTarget(target.to_string()));
+    }
+    Ok(matches)
+}
+
+// Registers a device found on the network, updating the IP if it's already known
+pub fn upsert_device(name: &str, ip: &str) {
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    match devices.iter_mut().find(|d| d.name.eq_ignore_ascii_case(name)) {
+        Some(device) => {
+            device.ip = ip.to_string();
+        },
+        None => {
+            devices.push(Device::new(name, ip));
+        }
+    }
+    settings::changed();
+}
+
+fn with_device<F>(devices: &mut [Device], name: &str, f: F) -> Result<(), String>
+where
+    F: FnOnce(&mut Device) -> Result<(), String>
+{
+    match devices.iter_mut().find(|d| d.name.eq_ignore_ascii_case(name)) {
+        Some(device) => f(device),
+        None => Err(format!("No device named '{}'", name))
+    }
+}
+
+fn with_shocker<F>(device: &mut Device, shocker: &str, f: F) -> Result<(), String>
+where
+    F: FnOnce(&mut Shocker) -> Result<(), String>
+{
+    let found = match shocker.parse::<u8>() {
+        Ok(index) => device.shockers.iter_mut().find(|s| s.index == index),
+        Err(_) => device.shockers.iter_mut().find(|s| s.name.eq_ignore_ascii_case(shocker))
+    };
+    match found {
+        Some(s) => f(s),
+        None => Err(format!("No shocker '{}' on device '{}'", shocker, device.name))
+    }
+}
+
+// Names have to fit the OSC address grammar, otherwise avatars could never trigger them
+fn check_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Names can't be empty".to_string());
+    }
+    if !is_target_name(name) {
+        return Err(format!("'{}' can only use letters, digits and '-', avatar parameters can't address anything else", name));
+    }
+    if name.parse::<u8>().is_ok() || name.eq_ignore_ascii_case(ALL_TARGET) || RESERVED_TARGETS.contains(&name) {
+        return Err(format!("'{}' is reserved and can't be used as a name", name));
+    }
+    Ok(())
+}
+
+
+#[tauri::command]
+pub fn get_devices() -> Vec<Device> {
+    QTSHOCK_DEVICES.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn add_device(name: String, ip: String) -> Result<Vec<Device>, String> {
+    check_name(&name)?;
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    if devices.iter().any(|d| d.name.eq_ignore_ascii_case(&name)) {
+        return Err(format!("A device named '{}' already exists", name));
+    }
+    devices.push(Device::new(&name, &ip));
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn remove_device(name: String) -> Result<Vec<Device>, String> {
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    let count = devices.len();
+    devices.retain(|d| !d.name.eq_ignore_ascii_case(&name));
+    if devices.len() == count {
+        return Err(format!("No device named '{}'", name));
+    }
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn rename_device(name: String, new_name: String) -> Result<Vec<Device>, String> {
+    check_name(&new_name)?;
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    if devices.iter().any(|d| d.name.eq_ignore_ascii_case(&new_name) && !d.name.eq_ignore_ascii_case(&name)) {
+        return Err(format!("A device named '{}' already exists", new_name));
+    }
+    with_device(&mut devices, &name, |device| {
+        device.name = new_name.clone();
+        Ok(())
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn set_device_ip(name: String, ip: String) -> Result<Vec<Device>, String> {
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    with_device(&mut devices, &name, |device| {
+        device.ip = ip.clone();
+        Ok(())
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn add_shocker(device: String, index: u8, name: String) -> Result<Vec<Device>, String> {
+    check_name(&name)?;
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    with_device(&mut devices, &device, |d| {
+        if d.shockers.iter().any(|s| s.index == index || s.name.eq_ignore_ascii_case(&name)) {
+            return Err(format!("Shocker {} or '{}' already exists on '{}'", index, name, d.name));
+        }
+        d.shockers.push(Shocker::new(index, &name));
+        Ok(())
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn remove_shocker(device: String, shocker: String) -> Result<Vec<Device>, String> {
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    with_device(&mut devices, &device, |d| {
+        let count = d.shockers.len();
+        match shocker.parse::<u8>() {
+            Ok(index) => d.shockers.retain(|s| s.index != index),
+            Err(_) => d.shockers.retain(|s| !s.name.eq_ignore_ascii_case(&shocker))
+        }
+        if d.shockers.len() == count {
+            return Err(format!("No shocker '{}' on device '{}'", shocker, d.name));
+        }
+        Ok(())
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn rename_shocker(device: String, shocker: String, new_name: String) -> Result<Vec<Device>, String> {
+    check_name(&new_name)?;
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    with_device(&mut devices, &device, |d| {
+        with_shocker(d, &shocker, |s| {
+            s.name = new_name.clone();
+            Ok(())
+        })
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn set_shocker_groups(device: String, shocker: String, groups: Vec<String>) -> Result<Vec<Device>, String> {
+    let groups: Vec<String> = groups.iter().map(|g| g.trim().to_string()).filter(|g| !g.is_empty()).collect();
+    for group in groups.iter() {
+        check_name(group)?;
+    }
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    with_device(&mut devices, &device, |d| {
+        with_shocker(d, &shocker, |s| {
+            s.groups = groups.clone();
+            Ok(())
+        })
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
+
+#[tauri::command]
+pub fn set_shocker_strengths(device: String, shocker: String, shock_strength: Option<u8>, vibrate_strength: Option<u8>) -> Result<Vec<Device>, String> {
+    for strength in [shock_strength, vibrate_strength].into_iter().flatten() {
+        if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+            return Err(format!("Strength must be between {} and {}", MIN_STRENGTH, MAX_STRENGTH));
+        }
+    }
+    let mut devices = QTSHOCK_DEVICES.lock().unwrap();
+    with_device(&mut devices, &device, |d| {
+        with_shocker(d, &shocker, |s| {
+            s.shock_strength = shock_strength;
+            s.vibrate_strength = vibrate_strength;
+            Ok(())
+        })
+    })?;
+    settings::changed();
+    Ok(devices.clone())
+}
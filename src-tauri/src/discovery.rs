@@ -0,0 +1,147 @@
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::sync::Mutex;
+use std::thread;
+use tauri::{AppHandle, Manager};
+
+// QTShock firmware advertises its web server, older builds only as plain http
+const QTSHOCK_SERVICE: &str = "_qtshock._tcp.local.";
+const HTTP_SERVICE: &str = "_http._tcp.local.";
+const QTSHOCK_HOSTNAME_PREFIX: &str = "qtshock";
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct DiscoveredDevice {
+    pub fullname: String,
+    pub hostname: String,
+    pub ip: String,
+    pub port: u16,
+    pub firmware_version: Option<String>
+}
+
+static DISCOVERED_DEVICES: Mutex<Vec<DiscoveredDevice>> = Mutex::new(Vec::new());
+static DISCOVERY_DAEMON: Mutex<Option<ServiceDaemon>> = Mutex::new(None);
+
+
+fn is_qtshock(service_type: &str, info: &ServiceInfo) -> bool {
+    if service_type == QTSHOCK_SERVICE {
+        return true;
+    }
+    info.get_hostname().to_lowercase().starts_with(QTSHOCK_HOSTNAME_PREFIX)
+        || info.get_fullname().to_lowercase().starts_with(QTSHOCK_HOSTNAME_PREFIX)
+}
+
+fn to_discovered(info: &ServiceInfo) -> Option<DiscoveredDevice> {
+    // Prefer IPv4, the firmware doesn't serve on IPv6
+    let ip = match info.get_addresses_v4().iter().next() {
+        Some(ip) => ip.to_string(),
+        None => info.get_addresses().iter().next()?.to_string()
+    };
+    let firmware_version = info.get_property_val_str("version")
+        .or(info.get_property_val_str("fw"))
+        .map(|v| v.to_string());
+    Some(DiscoveredDevice {
+        fullname: info.get_fullname().to_string(),
+        hostname: info.get_hostname().trim_end_matches('.').to_string(),
+        ip,
+        port: info.get_port(),
+        firmware_version
+    })
+}
+
+fn emit_devices(app: &AppHandle) {
+    let devices = DISCOVERED_DEVICES.lock().unwrap().clone();
+    let _ = app.emit_all("qtshock-discovery-event", devices);
+}
+
+fn browse_thread(app: AppHandle, daemon: ServiceDaemon, service_type: &'static str) {
+    let receiver = match daemon.browse(service_type) {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            println!("Failed to browse for {}: {}", service_type, e);
+            return;
+        }
+    };
+
+    while let Ok(event) = receiver.recv() {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                if !is_qtshock(service_type, &info) {
+                    continue;
+                }
+                let device = match to_discovered(&info) {
+                    Some(device) => device,
+                    None => {
+                        continue;
+                    }
+                };
+                println!("Discovered QTShock {} at {}", device.hostname, device.ip);
+                {
+                    let mut devices = DISCOVERED_DEVICES.lock().unwrap();
+                    devices.retain(|d| d.fullname != device.fullname);
+                    devices.push(device);
+                }
+                emit_devices(&app);
+            },
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                let removed = {
+                    let mut devices = DISCOVERED_DEVICES.lock().unwrap();
+                    let count = devices.len();
+                    devices.retain(|d| d.fullname != fullname);
+                    devices.len() != count
+                };
+                if removed {
+                    println!("QTShock {} left the network", fullname);
+                    emit_devices(&app);
+                }
+            },
+            ServiceEvent::SearchStopped(_) => {
+                break;
+            },
+            _ => {}
+        }
+    }
+    println!("Stopped browsing for {}", service_type);
+}
+
+pub fn discovered_devices() -> Vec<DiscoveredDevice> {
+    DISCOVERED_DEVICES.lock().unwrap().clone()
+}
+
+
+#[tauri::command]
+pub fn start_device_discovery(app: AppHandle) -> Result<Vec<DiscoveredDevice>, String> {
+    let mut daemon_lock = DISCOVERY_DAEMON.lock().unwrap();
+    if daemon_lock.is_some() {
+        return Ok(discovered_devices());
+    }
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            return Err(format!("Failed to start mDNS discovery: {}", e));
+        }
+    };
+    for service_type in [QTSHOCK_SERVICE, HTTP_SERVICE] {
+        let cloned_app = app.clone();
+        let cloned_daemon = daemon.clone();
+        thread::spawn(move || {
+            browse_thread(cloned_app, cloned_daemon, service_type);
+        });
+    }
+    *daemon_lock = Some(daemon);
+    Ok(discovered_devices())
+}
+
+#[tauri::command]
+pub fn stop_device_discovery() {
+    let daemon = DISCOVERY_DAEMON.lock().unwrap().take();
+    if let Some(daemon) = daemon {
+        if let Err(e) = daemon.shutdown() {
+            println!("Failed to stop mDNS discovery: {}", e);
+        }
+    }
+    DISCOVERED_DEVICES.lock().unwrap().clear();
+}
+
+#[tauri::command]
+pub fn get_discovered_devices() -> Vec<DiscoveredDevice> {
+    discovered_devices()
+}
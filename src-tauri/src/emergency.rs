@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, GlobalShortcutManager, Manager};
+use tokio::sync::Notify;
+
+use crate::client::{qtshock_client, DeviceError};
+use crate::cs::CsService;
+use crate::devices::QTSHOCK_DEVICES;
+use crate::vrc::VrcService;
+use crate::Payload;
+
+pub const EMERGENCY_SHORTCUT: &str = "CmdOrCtrl+Shift+F12";
+pub const EMERGENCY_OSC_ADDRESSES: [&str; 2] = ["/avatar/parameters/QTS_PANIC", "/qthub/panic"];
+
+static EMERGENCY_STOP: Mutex<bool> = Mutex::new(false);
+
+#[derive(Clone, serde::Serialize)]
+struct EmergencyPayload {
+    active: bool,
+    source: String
+}
+
+
+fn notifier() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+pub fn is_active() -> bool {
+    *EMERGENCY_STOP.lock().unwrap()
+}
+
+pub fn check() -> Result<(), DeviceError> {
+    if is_active() {
+        return Err(DeviceError::EmergencyStop);
+    }
+    Ok(())
+}
+
+// Runs a device request, dropping it mid-flight if the emergency stop is engaged
+pub async fn abortable<F>(request: F) -> Result<(), DeviceError>
+where
+    F: Future<Output = Result<(), DeviceError>>
+{
+    // Created before the check so a stop engaged in between still wakes us
+    let stopped = notifier().notified();
+    check()?;
+    tokio::select! {
+        res = request => res,
+        _ = stopped => Err(DeviceError::EmergencyStop)
+    }
+}
+
+pub async fn engage(app: &AppHandle, source: &str, notify_devices: bool) {
+    *EMERGENCY_STOP.lock().unwrap() = true;
+    notifier().notify_waiters();
+    crate::patterns::cancel_all();
+
+    // Last chance to tell the avatar before the integration goes away
+    let vrc = app.state::<VrcService>();
+    vrc.feedback.publish(&vrc);
+    vrc.stop();
+    app.state::<CsService>().stop().await;
+    // Integrations stay off after a restart until they're turned back on
+    crate::settings::set_integration(Some(false), Some(false));
+
+    println!("EMERGENCY STOP engaged from {}", source);
+    let message = format!("EMERGENCY STOP engaged from {}. All integrations have been stopped.", source);
+    let _ = app.emit_all("vrc-osc-event", Payload { message: message.clone() });
+    let _ = app.emit_all("cs-rust-event", Payload { message });
+    let _ = app.emit_all("emergency-stop-event", EmergencyPayload { active: true, source: source.to_string() });
+
+    if !notify_devices {
+        return;
+    }
+    let devices = QTSHOCK_DEVICES.lock().unwrap().clone();
+    for device in devices {
+        match qtshock_client().stop(&device.ip).await {
+            Ok(()) => {},
+            Err(e) => {
+                println!("Failed to send stop to {}: {}", device.name, e);
+            }
+        }
+    }
+}
+
+pub fn rearm(app: &AppHandle, source: &str) {
+    *EMERGENCY_STOP.lock().unwrap() = false;
+    println!("Emergency stop re-armed from {}", source);
+    let _ = app.emit_all("emergency-stop-event", EmergencyPayload { active: false, source: source.to_string() });
+}
+
+pub fn register_shortcut(app: &AppHandle) {
+    let cloned_app = app.clone();
+    let mut shortcuts = app.global_shortcut_manager();
+    match shortcuts.register(EMERGENCY_SHORTCUT, move || {
+        let app = cloned_app.clone();
+        tauri::async_runtime::spawn(async move {
+            engage(&app, "hotkey", true).await;
+        });
+    }) {
+        Ok(()) => {
+            println!("Registered emergency stop hotkey {}", EMERGENCY_SHORTCUT);
+        },
+        Err(e) => {
+            println!("Failed to register emergency stop hotkey {}: {}", EMERGENCY_SHORTCUT, e);
+        }
+    }
+}
+
+
+#[tauri::command]
+pub async fn emergency_stop(app: AppHandle, notify_devices: bool) {
+    engage(&app, "app", notify_devices).await;
+}
+
+#[tauri::command]
+pub fn rearm_emergency_stop(app: AppHandle) {
+    rearm(&app, "app");
+}
+
+#[tauri::command]
+pub fn get_emergency_stop() -> bool {
+    is_active()
+}
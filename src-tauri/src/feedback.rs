@@ -0,0 +1,135 @@
+use rosc::OscType;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::DeviceError;
+use crate::defines::QTSInteraction;
+use crate::devices::{Device, QTSHOCK_DEVICES};
+use crate::emergency;
+use crate::safety::QTSHOCK_SAFETY;
+use crate::vrc::VrcService;
+
+// Cooldowns are short, anything slower would miss most of them
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(250);
+
+pub const CONNECTED_PARAMETER: &str = "QTS_OUT_CONNECTED";
+pub const COOLDOWN_PARAMETER: &str = "QTS_OUT_COOLDOWN";
+pub const LOCKED_PARAMETER: &str = "QTS_OUT_LOCKED";
+pub const LAST_INTERACTION_PARAMETER: &str = "QTS_OUT_LAST";
+
+
+// Same scale VRChat radial menus use, 0 to 1
+pub fn strength_value(strength: u8) -> OscType {
+    OscType::Float((strength.saturating_sub(1) as f32) / 100.0)
+}
+
+/*
+    Per-shocker strength parameters follow the trigger grammar in osc_address.rs:
+    QTS_OUT_<index>_STRENGTH for the first device, QTS_OUT_<device>_<index>_STRENGTH otherwise,
+    with anything but letters, digits and "-" dropped from the device name.
+*/
+fn strength_parameter(first: bool, device: &Device, index: u8) -> String {
+    if first {
+        return format!("QTS_OUT_{}_STRENGTH", index);
+    }
+    let device: String = device.name.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+    format!("QTS_OUT_{}_{}_STRENGTH", device, index)
+}
+
+fn interaction_value(interaction: Option<QTSInteraction>) -> OscType {
+    OscType::Int(match interaction {
+        None => 0,
+        Some(QTSInteraction::SHOCK) => 1,
+        Some(QTSInteraction::VIBRATE) => 2,
+        Some(QTSInteraction::BEEP) => 3
+    })
+}
+
+/*
+    What gets published back to the avatar, kept on the VRChat service. Remembers what VRChat
+    was last told so only changes are sent.
+*/
+pub struct Feedback {
+    sent: Mutex<HashMap<String, OscType>>,
+    // Whether the last request to each device reached it, devices we haven't talked to yet count as online
+    device_online: Mutex<BTreeMap<String, bool>>,
+    last_interaction: Mutex<Option<QTSInteraction>>
+}
+
+impl Feedback {
+    pub fn new() -> Self {
+        Self { sent: Mutex::new(HashMap::new()), device_online: Mutex::new(BTreeMap::new()), last_interaction: Mutex::new(None) }
+    }
+
+    pub fn record_result(&self, device: &str, result: &Result<(), DeviceError>) {
+        let online = !matches!(result, Err(DeviceError::Connection(_)) | Err(DeviceError::Timeout) | Err(DeviceError::NoDevice));
+        self.device_online.lock().unwrap().insert(device.to_string(), online);
+    }
+
+    pub fn record_interaction(&self, interaction: QTSInteraction) {
+        *self.last_interaction.lock().unwrap() = Some(interaction);
+    }
+
+    // A new avatar starts from its defaults, everything has to be sent again
+    pub fn resync(&self) {
+        self.sent.lock().unwrap().clear();
+    }
+
+    // Everything QTHub publishes, as it stands right now
+    fn current_parameters(&self) -> Vec<(String, OscType)> {
+        let devices = QTSHOCK_DEVICES.lock().unwrap().clone();
+        let connected = {
+            let online = self.device_online.lock().unwrap();
+            devices.iter().any(|d| !d.ip.is_empty() && *online.get(&d.name).unwrap_or(&true))
+        };
+        let mut parameters = vec![
+            (CONNECTED_PARAMETER.to_string(), OscType::Bool(connected)),
+            (COOLDOWN_PARAMETER.to_string(), OscType::Bool(QTSHOCK_SAFETY.lock().unwrap().cooling_down())),
+            (LOCKED_PARAMETER.to_string(), OscType::Bool(emergency::is_active())),
+            (LAST_INTERACTION_PARAMETER.to_string(), interaction_value(*self.last_interaction.lock().unwrap()))
+        ];
+        let shock_strength = *crate::QTSHOCK_SHK_STRENGTH.lock().unwrap();
+        for (i, device) in devices.iter().enumerate() {
+            for shocker in device.shockers.iter() {
+                let strength = shocker.shock_strength.unwrap_or(shock_strength);
+                parameters.push((strength_parameter(i == 0, device, shocker.index), strength_value(strength)));
+            }
+        }
+        parameters
+    }
+
+    pub fn publish(&self, vrc: &VrcService) {
+        for (name, value) in self.current_parameters() {
+            if self.sent.lock().unwrap().get(&name) == Some(&value) {
+                continue;
+            }
+            match vrc.send_parameter(&name, value.clone()) {
+                Ok(()) => {
+                    self.sent.lock().unwrap().insert(name, value);
+                },
+                Err(e) => {
+                    println!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+// Publishes for as long as the VRChat integration runs
+pub fn spawn_publisher(app: AppHandle, cancel: CancellationToken) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(PUBLISH_INTERVAL) => {}
+            }
+            let vrc = app.state::<VrcService>();
+            if vrc.osc_config().feedback {
+                vrc.feedback.publish(&vrc);
+            }
+        }
+    });
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/*
+    When a float parameter counts as pressed. It fires once above `on_threshold` (after being
+    held there for `min_hold_ms`) and only fires again once it has dropped below
+    `off_threshold` and `rearm_delay_ms` has passed.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hysteresis {
+    pub on_threshold: f32,
+    pub off_threshold: f32,
+    pub min_hold_ms: u32,
+    pub rearm_delay_ms: u32
+}
+
+impl Default for Hysteresis {
+    fn default() -> Self {
+        Self { on_threshold: 0.8, off_threshold: 0.2, min_hold_ms: 0, rearm_delay_ms: 0 }
+    }
+}
+
+impl Hysteresis {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.on_threshold) || !(0.0..=1.0).contains(&self.off_threshold) {
+            return Err("Thresholds must be between 0 and 1".to_string());
+        }
+        if self.off_threshold >= self.on_threshold {
+            return Err("The release threshold must be below the trigger threshold".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LatchEvent {
+    Fire,
+    // Crossed the threshold, fire once the hold time has passed and `confirm` agrees
+    Hold(u64),
+    Release,
+    Nothing
+}
+
+#[derive(Default)]
+struct Latch {
+    value: f32,
+    fired: bool,
+    pending: Option<u64>,
+    released_at: Option<Instant>
+}
+
+// One latch per OSC address so separate contacts don't block each other
+pub struct Latches {
+    latches: Mutex<HashMap<String, Latch>>,
+    counter: Mutex<u64>
+}
+
+impl Latches {
+    pub fn new() -> Self {
+        Self { latches: Mutex::new(HashMap::new()), counter: Mutex::new(0) }
+    }
+
+    pub fn update(&self, key: &str, value: f32, hysteresis: &Hysteresis) -> LatchEvent {
+        let mut latches = self.latches.lock().unwrap();
+        let latch = latches.entry(key.to_string()).or_default();
+        latch.value = value;
+
+        if value > hysteresis.on_threshold {
+            if latch.fired || latch.pending.is_some() {
+                return LatchEvent::Nothing;
+            }
+            if let Some(released_at) = latch.released_at {
+                if released_at.elapsed() < Duration::from_millis(hysteresis.rearm_delay_ms as u64) {
+                    return LatchEvent::Nothing;
+                }
+            }
+            if hysteresis.min_hold_ms == 0 {
+                latch.fired = true;
+                return LatchEvent::Fire;
+            }
+            let hold = {
+                let mut counter = self.counter.lock().unwrap();
+                *counter += 1;
+                *counter
+            };
+            latch.pending = Some(hold);
+            return LatchEvent::Hold(hold);
+        }
+
+        if value < hysteresis.off_threshold {
+            latch.pending = None;
+            if latch.fired {
+                latch.fired = false;
+                latch.released_at = Some(Instant::now());
+                return LatchEvent::Release;
+            }
+        }
+        LatchEvent::Nothing
+    }
+
+    // True if the value stayed up for the whole hold, the latch is then fired
+    pub fn confirm(&self, key: &str, hold: u64) -> bool {
+        let mut latches = self.latches.lock().unwrap();
+        match latches.get_mut(key) {
+            Some(latch) if latch.pending == Some(hold) => {
+                latch.pending = None;
+                latch.fired = true;
+                true
+            },
+            _ => false
+        }
+    }
+
+    // The latest value while the latch is fired, None once it's released
+    pub fn held_value(&self, key: &str) -> Option<f32> {
+        match self.latches.lock().unwrap().get(key) {
+            Some(latch) if latch.fired => Some(latch.value),
+            _ => None
+        }
+    }
+
+    pub fn clear(&self) {
+        self.latches.lock().unwrap().clear();
+    }
+}
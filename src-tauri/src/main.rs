@@ -1,559 +1,245 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use defines::{QTSInteraction, QTSOSCType};
+use defines::{QTSAction, QTSInteraction};
 use tauri::async_runtime::block_on;
-use tauri::{App, AppHandle, Manager};
-use rosc::{OscPacket, OscType, OscMessage};
-use std::collections::HashMap;
-use std::fs;
-use std::env;
-use std::io::Read;
-use std::net::{SocketAddrV4, UdpSocket};
-use std::str::FromStr;
+use tauri::{AppHandle, Manager, State};
 use std::sync::Mutex;
-use std::thread;
-use std::time::Duration;
-use std::path::PathBuf;
-use rfd::FileDialog;
-use rosc::encoder;
-
-use poem::{
-    handler, listener::TcpListener, post,
-    Route, Server, web::Json
-};
-use gsi_cs2::{player::MatchStats, provider::Provider, Body};
 
 
 use dns_lookup::lookup_host;
 
-use reqwest;
+use client::{qtshock_client, check_strength, parse_strength, DeviceError};
+use safety::{SafetyVerdict, QTSHOCK_SAFETY};
+use devices::ShockerTarget;
+use avatars::StrengthCaps;
+use recording::DryRun;
 
 mod gsi_cfg;
 mod defines;
 mod firmware;
+mod client;
+mod devices;
+mod discovery;
+mod safety;
+mod emergency;
+mod api;
+mod patterns;
+mod settings;
+mod vrc;
+mod avatars;
+mod chatbox;
+mod feedback;
+mod oscquery;
+mod osc_address;
+mod osc_filter;
+mod latch;
+mod mappings;
+mod proportional;
+mod recording;
+mod cs;
+mod status;
 
 static QTSHOCK_SHK_STRENGTH: Mutex<u8> = Mutex::new(10);
 static QTSHOCK_VIB_STRENGTH: Mutex<u8> = Mutex::new(80);
-static QTSHOCK_IP: Mutex<String> = Mutex::new(String::new());
-
-static VRC_OSC_THREAD: Mutex<bool> = Mutex::new(true);
-static VRC_OSC_SENDER: Mutex<Option<UdpSocket>> = Mutex::new(None);
-static VRC_OSC_CANSHOCK: Mutex<bool> = Mutex::new(true);
-
-static CS_GSI_THREAD: Mutex<bool> = Mutex::new(false);
-static CS_CURRENT_DEATH_COUNT: Mutex<u16> = Mutex::new(0);
-
 
 
 #[tauri::command]
-async fn set_shock_strength(strength: u8) {
+fn set_shock_strength(vrc: State<'_, vrc::VrcService>, strength: u8) -> Result<(), String> {
+    let strength = check_strength(strength)?;
     *QTSHOCK_SHK_STRENGTH.lock().unwrap() = strength;
-    let to_addr = match SocketAddrV4::from_str("127.0.0.1:9000") {
-        Ok(addr) => {
-            println!("Got proper address!");
-            addr
-        },
-        Err(_) => {
-            println!("FAILED TO GET ADDRESS!");
-            return;
-        }
-    };
-    let mutex_sock = VRC_OSC_SENDER.lock().unwrap();
-    // switch view
-    let msg_buf = encoder::encode(&OscPacket::Message(OscMessage {
-        addr: "/avatar/parameters/QTS_IN_SHOCK_STRENGTH".to_string(),
-        args: vec![OscType::Float(((*QTSHOCK_SHK_STRENGTH.lock().unwrap() - 1) as f32) / 100.0)],
-    }))
-    .unwrap();
-
-    mutex_sock.as_ref().unwrap().send_to(&msg_buf, to_addr).unwrap();
+    settings::changed();
+    vrc.send_strength(QTSInteraction::SHOCK, strength);
+    Ok(())
 }
 
 #[tauri::command]
-async fn set_vibrate_strength(strength: u8) {
+fn set_vibrate_strength(vrc: State<'_, vrc::VrcService>, strength: u8) -> Result<(), String> {
+    let strength = check_strength(strength)?;
     *QTSHOCK_VIB_STRENGTH.lock().unwrap() = strength;
-    let to_addr = match SocketAddrV4::from_str("127.0.0.1:9000") {
-        Ok(addr) => {
-            println!("Got proper address!");
-            addr
-        },
-        Err(_) => {
-            println!("FAILED TO GET ADDRESS!");
-            return;
-        }
-    };
-    let mutex_sock = VRC_OSC_SENDER.lock().unwrap();
-    // switch view
-    let msg_buf = encoder::encode(&OscPacket::Message(OscMessage {
-        addr: "/avatar/parameters/QTS_IN_VIBRATE_STRENGTH".to_string(),
-        args: vec![OscType::Float(((*QTSHOCK_VIB_STRENGTH.lock().unwrap() - 1) as f32) / 100.0)],
-    }))
-    .unwrap();
-
-    mutex_sock.as_ref().unwrap().send_to(&msg_buf, to_addr).unwrap();
-}
-
-
-
-
-
-async fn death_check(data: Json<gsi_cs2::Body>) {
-    let player = match data.player.as_ref() {
-        Some(plyr) => {
-            plyr
-        },
-        None => {
-            return;
-        }
-    };
-    let match_stats = match player.match_stats.as_ref() {
-        Some(p_stats) => {
-            p_stats
-        },
-        None => {
-            return;
-        }
-    };
-    let provider = match data.provider.as_ref() {
-        Some(p_provider) => {
-            p_provider
-        },
-        None => {
-            return;
-        }
-    };
-
-    if provider.steam_id != player.steam_id.clone().unwrap() {
-        return;
-    }
-
-    if *CS_CURRENT_DEATH_COUNT.lock().unwrap() > match_stats.deaths {
-        *CS_CURRENT_DEATH_COUNT.lock().unwrap() = 0;
-    }
-
-    if *CS_CURRENT_DEATH_COUNT.lock().unwrap() < match_stats.deaths {
-        *CS_CURRENT_DEATH_COUNT.lock().unwrap() = match_stats.deaths;
-        println!("Player died!");
-        let mut map = HashMap::new();
-        map.insert("_type", 1);
-        map.insert("Strength", 2);
-
-        let _ = trigger_qtshock(0, QTSInteraction::SHOCK).await;
-    }
+    settings::changed();
+    vrc.send_strength(QTSInteraction::VIBRATE, strength);
+    Ok(())
 }
 
 
-
-
-
-
-
-
-
 #[derive(Clone, serde::Serialize)]
 struct Payload {
     message: String
 }
 
-async fn trigger_qtshock(shocker: u8, interaction: QTSInteraction) -> Result<(), String>{
+fn default_strength(interaction: QTSInteraction, shocker: &ShockerTarget) -> u8 {
     match interaction {
-        QTSInteraction::SHOCK => {
-            let strength = QTSHOCK_SHK_STRENGTH.lock().unwrap().to_string().clone();
-            shock(shocker, strength.as_str()).await;
-            Ok(())
-        },
-        QTSInteraction::VIBRATE => {
-            let strength = QTSHOCK_SHK_STRENGTH.lock().unwrap().to_string().clone();
-            vibrate(shocker, strength.as_str()).await;
-            Ok(())
-        },
-        QTSInteraction::BEEP => {
-            beep(shocker).await;
-            Ok(())
-        }
+        QTSInteraction::SHOCK => shocker.shock_strength.unwrap_or(*QTSHOCK_SHK_STRENGTH.lock().unwrap()),
+        QTSInteraction::VIBRATE => shocker.vibrate_strength.unwrap_or(*QTSHOCK_VIB_STRENGTH.lock().unwrap()),
+        QTSInteraction::BEEP => 0
     }
 }
 
-#[tauri::command]
-fn create_cs_config(app: AppHandle) {
-    let folder: Option<PathBuf> = FileDialog::new()
-        .set_directory("/")
-        .set_title("Select the Counter Strike 2 game directory")
-        .pick_folder();
-
-    let cs_path: PathBuf = match folder {
-        Some(path) => path,
-        None => {
-            app.emit_all("cs-rust-event", Payload { message: "Setup failed. You must select your CS2 game directory to set up QTShock integration.".to_string() });
-            return;
-        }
+/*
+    What a trigger carries besides its target and strength. `caps` are the worn avatar's and
+    `dry_run` is set for a dry-run replay's own triggers, the default is a plain live trigger.
+*/
+#[derive(Clone, Default)]
+pub struct TriggerScope {
+    pub caps: StrengthCaps,
+    pub dry_run: Option<DryRun>
+}
+
+/*
+    Sends an interaction to every shocker the target resolves to, after applying the safety limits.
+    Without an explicit strength each shocker uses its own default, then the global strength.
+    The avatar's caps go on per shocker before the safety caps, the duration is capped once for all of them.
+*/
+async fn trigger_qtshock(app: &AppHandle, target: &str, interaction: QTSInteraction, strength: Option<u8>, duration_ms: Option<u32>, scope: &TriggerScope) -> Result<SafetyVerdict, DeviceError> {
+    emergency::check()?;
+    let shockers = devices::resolve_target(target)?;
+    let safety = match &scope.dry_run {
+        Some(dry_run) => dry_run.safety(),
+        None => &QTSHOCK_SAFETY
     };
-
-    let path_str = match cs_path.to_str() {
-        Some(path) => path,
-        None => {
-            app.emit_all("cs-rust-event", Payload { message: "Setup failed. Something is wrong with the name of your selected directory.".to_string() });
-            return;
+    let checked = safety.lock().unwrap().check(interaction);
+    let mut verdict = match checked {
+        Ok(verdict) => verdict,
+        Err(reason) => {
+            return Err(DeviceError::Blocked(reason));
         }
     };
-
-    if !path_str.ends_with("Counter-Strike Global Offensive") {
-        app.emit_all("cs-rust-event", Payload { message: "Setup failed. This is not the path to CS2..".to_string() });
-        return;
-    }
-
-    let gsi_config: gsi_cfg::gsi_cfg = gsi_cfg::gsi_cfg {
-        uri: "http://127.0.0.1:3005".to_string(),
-        timeout: "1.0".to_string(),
-        buffer: "0.0".to_string(),
-        throttle: "0.0".to_string(),
-        heartbeat: "60.0".to_string(),
-        auth: gsi_cfg::gsi_auth { token: "TOKEN".to_string() },
-        output: gsi_cfg::gsi_output { precision: "3".to_string(), precision_position: "1".to_string(), precision_vector: "3".to_string() },
-        data: gsi_cfg::gsi_data {
-            map_round_wins: "1".to_string(),
-            map: "1".to_string(),
-            player_id: "1".to_string(),
-            player_match_stats: "1".to_string(),
-            player_state: "1".to_string(),
-            player_weapons: "1".to_string(),
-            provider: "1".to_string(),
-            round: "1".to_string(),
-            allgrenades: "1".to_string(),
-            allplayers_id: "1".to_string(),
-            allplayers_match_stats: "1".to_string(),
-            allplayers_position: "1".to_string(),
-            allplayers_state: "1".to_string(),
-            allplayers_weapons: "1".to_string(),
-            bomb: "1".to_string(),
-            phase_countdowns: "1".to_string(),
-            player_position: "1".to_string()
-        }
-
-    };
-    let gsi_config_data = match vdf_serde::to_string(&gsi_config) {
-        Ok(data) => {
-            println!("{}", data);
-            data
-        },
-        Err(e) => {
-            app.emit_all("cs-rust-event", Payload { message: "Setup failed. Something went wrong when creating the gsi config.".to_string() });
-            println!("{}", e);
-            return;
-        }
-    };
-    fs::write(path_str.to_string() + "/game/csgo/cfg/gamestate_integration_qtshock.cfg", gsi_config_data);
-}
-
-#[tauri::command]
-fn start_cs_listener(app: AppHandle, start: bool) {
-    *CS_GSI_THREAD.lock().unwrap() = start;
-    if !start {
-        return;
-    }
-    let cloned_app = app.clone();
-    let _new_thread = thread::spawn(|| {
-        block_on(async {
-            cs_thread(cloned_app).await;
-        })
-    });
-    let _ = block_on(beep(0));
-    let _ = app.emit_all("cs-rust-event", Payload { message: format!("Toggled CS2 integration ON").into() });
-}
-
-#[handler]
-async fn cs_update(data: Json<gsi_cs2::Body>) {
-
-    death_check(data.clone()).await;
-}
-
-async fn stop_gsi_thread(app: AppHandle) {
-    loop {
-        let keep_thread: bool = *CS_GSI_THREAD.lock().unwrap();
-        if !keep_thread {
-            break;
+    // A downgraded interaction shouldn't inherit the shock strength
+    let strength = if verdict.interaction == interaction { strength } else { None };
+
+    let mut reasons: Vec<String> = verdict.reason.take().into_iter().collect();
+    let (duration_ms, capped) = safety.lock().unwrap().cap_duration(duration_ms);
+    reasons.extend(capped);
+    let feedback = &app.state::<vrc::VrcService>().feedback;
+    let mut result = Ok(());
+    for shocker in shockers {
+        let mut requested = strength.unwrap_or_else(|| default_strength(verdict.interaction, &shocker));
+        if let Some(cap) = scope.caps.get(verdict.interaction).filter(|cap| requested > *cap) {
+            reasons.push(format!("Strength {} capped to {} for this avatar on {}/{}", requested, cap, shocker.device, shocker.shocker));
+            requested = cap;
+        }
+        let (strength, capped) = safety.lock().unwrap().cap_strength(verdict.interaction, requested, &shocker);
+        if let Some(reason) = capped {
+            reasons.push(reason);
+        }
+        verdict.strength = verdict.strength.max(strength);
+        if let Some(dry_run) = &scope.dry_run {
+            dry_run.send(&shocker, verdict.interaction, strength, duration_ms);
+            continue;
+        }
+        let res = match verdict.interaction {
+            QTSInteraction::SHOCK => emergency::abortable(qtshock_client().shock(&shocker.ip, shocker.shocker, strength, duration_ms)).await,
+            QTSInteraction::VIBRATE => emergency::abortable(qtshock_client().vibrate(&shocker.ip, shocker.shocker, strength, duration_ms)).await,
+            QTSInteraction::BEEP => emergency::abortable(qtshock_client().beep(&shocker.ip, shocker.shocker, duration_ms)).await
+        };
+        feedback.record_result(&shocker.device, &res);
+        if let Err(e) = res {
+            println!("Failed to {:?} {}/{}: {}", verdict.interaction, shocker.device, shocker.shocker, e);
+            result = Err(e);
         }
     }
-    app.emit_all("cs-rust-event", Payload { message: format!("Toggled CS2 integration OFF").into() });
-}
-
-async fn cs_thread(app: AppHandle) -> Result<(), std::io::Error>{
-    tracing_subscriber::fmt::try_init();
-    let gsi_webserver = Route::new().at("/", post(cs_update));
-    let cloned_app = app.clone();
-    let _server = match Server::new(TcpListener::bind("127.0.0.1:3005"))
-        .run_with_graceful_shutdown(gsi_webserver,
-        async move {
-            stop_gsi_thread(cloned_app).await
-        },
-        Some(Duration::from_secs(5)))
-        .await {
-            Ok(()) => {
-                
-            },
-            Err(e) => {
-                let _ = app.emit_all("cs-rust-event", Payload { message: format!("Something went wrong when starting the CS2 integration. The port provided is already in use.").into() });
-            }
-        };
-    Ok(())
-}
-
-
-#[tauri::command]
-fn start_vrc_osc(app: AppHandle, start: bool) {
-    *VRC_OSC_THREAD.lock().unwrap() = start;
-    if !start {
-        return;
+    if !reasons.is_empty() {
+        let reason = reasons.join("; ");
+        println!("Safety limits applied: {}", reason);
+        verdict.reason = Some(reason);
     }
-    let _new_thread = thread::spawn(|| {
-        vrc_osc_thread(app);
-    });
-    let _new_thread = thread::spawn(|| {
-        vrc_osc_send_thread();
-    });
-    let _ = block_on(beep(0));
-}
-
-
-
-async fn handle_packet(app: &AppHandle, packet: OscPacket) {
-    let keep_thread: bool = *VRC_OSC_THREAD.lock().unwrap();
-    if !keep_thread {
-        return;
+    if result.is_ok() && scope.dry_run.is_none() {
+        feedback.record_interaction(verdict.interaction);
     }
-    match packet {
-        OscPacket::Message(msg) => {
-            if !msg.addr.contains("QTS_") {
-                return;
-            }
-            app.emit_all("vrc-osc-event", Payload { message: format!("VRC OSC msg | {}: {:?}", msg.addr, msg.args).into() }).unwrap();
-            let addr_parts: Vec<&str> = msg.addr.split("_").collect();
-            let shocker_index: u8 = match addr_parts[1].parse::<u8>() {
-                Ok(index) => index,
-                Err(e) => {
-                    app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. Bad invalid shocker index.").into() }).unwrap();
-                    0
-                }
-            };
-            let qt_osc_type: QTSOSCType = match QTSOSCType::from_str(addr_parts[2]) {
-                Ok(osc_type) => osc_type,
-                _ => {
-                    app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. Bad command type.").into() }).unwrap();
-                    return;
-                }
-            };
-
-            let qt_osc_interaction: QTSInteraction = match QTSInteraction::from_str(addr_parts[3]) {
-                Ok(osc_interaction) => osc_interaction,
-                _ => {
-                    app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. Bad interaction type.").into() }).unwrap();
-                    return;
-                }
-            };
-
-            match qt_osc_type {
-                QTSOSCType::PUSH => {
-                    match msg.args[0] {
-                        OscType::Float(f) => {
-                            if f > 0.8f32 && *VRC_OSC_CANSHOCK.lock().unwrap() == true {
-                                *VRC_OSC_CANSHOCK.lock().unwrap() = false;
-                                match trigger_qtshock(shocker_index, qt_osc_interaction).await {
-                                    Ok(()) => {
-                                        app.emit_all("vrc-osc-event", Payload { message: format!("Boop").into() }).unwrap();
-                                    },
-                                    _ => {
-                                        app.emit_all("vrc-osc-event", Payload { message: format!("Something went wrong when triggering your QTShock.").into() }).unwrap();
-                                    }
-
-                                }
-                            }
-                            if f < 0.2f32 && *VRC_OSC_CANSHOCK.lock().unwrap() == false {
-                                *VRC_OSC_CANSHOCK.lock().unwrap() = true;
-                                app.emit_all("vrc-osc-event", Payload { message: format!("Unboop").into() }).unwrap();
-                            }
-                        },
-                        _ => {
-                            app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. Bad value type.").into() }).unwrap();
-                        }
-                    }
-                },
-                QTSOSCType::HIT => {
-                    match msg.args[0] {
-                        OscType::Bool(b) => {
-                            app.emit_all("vrc-osc-event", Payload { message: format!("HIT!!!!!!!!!!!!!!!!!!!!").into() }).unwrap();
-                            if b {
-                                match trigger_qtshock(shocker_index, qt_osc_interaction).await {
-                                    Ok(()) => {
-                                    },
-                                    _ => {
-                                        app.emit_all("vrc-osc-event", Payload { message: format!("Something went wrong when triggering your QTShock.").into() }).unwrap();
-                                    }
-    
-                                }
-                            }
-                        },
-                        _ => {
-                            app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. Bad value type.").into() }).unwrap();
-                        }
-                    }
-                }
-            }
-        }
-        OscPacket::Bundle(bundle) => {
-            app.emit_all("vrc-osc-event", Payload { message: format!("VRC OSC bundle | {:?}", bundle).into() }).unwrap();
-
-        }
-    }
-}
-
-fn vrc_osc_send_thread() {
-    let host_addr = match SocketAddrV4::from_str("127.0.0.1:7766") {
-        Ok(addr) => {
-            println!("Got proper address!");
-            addr
-        },
-        Err(_) => {
-            println!("FAILED TO GET ADDRESS!");
-            return;
-        }
-    };
-    let to_addr = match SocketAddrV4::from_str("127.0.0.1:9000") {
-        Ok(addr) => {
-            println!("Got proper address!");
-            addr
-        },
-        Err(_) => {
-            println!("FAILED TO GET ADDRESS!");
-            return;
-        }
-    };
-    let send_sock = UdpSocket::bind(host_addr).unwrap();
-    let mut mutex_sock = VRC_OSC_SENDER.lock().unwrap();
-    *mutex_sock = Some(send_sock);
-    // switch view
-    let msg_buf = encoder::encode(&OscPacket::Message(OscMessage {
-        addr: "/avatar/parameters/QTS_IN_SHOCK_STRENGTH".to_string(),
-        args: vec![OscType::Float(((*QTSHOCK_SHK_STRENGTH.lock().unwrap() - 1) as f32) / 100.0)],
-    }))
-    .unwrap();
-
-    mutex_sock.as_ref().unwrap().send_to(&msg_buf, to_addr).unwrap();
-
+    result.map(|_| verdict)
 }
 
-fn vrc_osc_thread(app: AppHandle) {
-
-    let addr = match SocketAddrV4::from_str("127.0.0.1:9001") {
-        Ok(addr) => {
-            println!("Got proper address!");
-            addr
+// Runs the configured action for a trigger, patterns play in the background so have no verdict
+async fn trigger_action(app: &AppHandle, target: &str, action: &QTSAction, strength: Option<u8>, scope: &TriggerScope) -> Result<Option<SafetyVerdict>, DeviceError> {
+    match action {
+        QTSAction::Interaction(interaction) => {
+            let verdict = trigger_qtshock(app, target, *interaction, strength, None, scope).await?;
+            Ok(Some(verdict))
         },
-        Err(_) => {
-            println!("FAILED TO GET ADDRESS!");
-            return;
-        }
-    };
-    let sock = UdpSocket::bind(addr).unwrap();
-    app.emit_all("vrc-osc-event", Payload { message: format!("Listening to {}", addr).into() }).unwrap();
-    println!("Listening to {}", addr);
-
-    let mut buf = [0u8; rosc::decoder::MTU];
-
-    loop {
-        let keep_thread: bool = *VRC_OSC_THREAD.lock().unwrap();
-        if !keep_thread {
-            break;
-        }
-        match sock.recv_from(&mut buf) {
-            Ok((size, _addr)) => {
-                let (_, packet) = rosc::decoder::decode_udp(&buf[..size]).unwrap();
-                block_on(handle_packet(&app, packet));
-            }
-            Err(e) => {
-                println!("Error receiving from socket: {}", e);
-                break;
-            }
+        QTSAction::Pattern(name) => {
+            emergency::check()?;
+            patterns::start_pattern(app, target, name, scope.clone())?;
+            Ok(None)
         }
     }
-    println!("VRC OSC Socket closed!");
-    app.emit_all("vrc-osc-event", Payload { message: "VRC OSC Socket closed".into() }).unwrap();
 }
 
-
 #[tauri::command]
 fn load_local_ip() -> String {
     let hostname = "qtshock.local";
     match lookup_host(hostname) {
         Ok(ips) => {
-            *QTSHOCK_IP.lock().unwrap() = ips[0].to_string();
+            devices::upsert_device("QTShock", &ips[0].to_string());
             ips[0].to_string()
         },
         Err(err) => {
-            format!("Failed to find a QTShock on the network! Error: {}", err.to_string()).to_string()
+            // The OS resolver often can't see .local names, fall back to our own mDNS browsing
+            match discovery::discovered_devices().first() {
+                Some(device) => {
+                    devices::upsert_device("QTShock", &device.ip);
+                    device.ip.clone()
+                },
+                None => {
+                    format!("Failed to find a QTShock on the network! Error: {}", err.to_string()).to_string()
+                }
+            }
         }
     }
     
 }
 
-#[tauri::command]
-async fn shock(shocker: u8, strength: &str) -> Result<String, String> {
-    match strength.to_string().parse::<i16>() {
-        Ok(i) => {
-            if i < 1 || i > 99 {
-                return Err("".to_string());
-            }
-            let params = [("shocker", shocker.to_string()), ("strength", strength.to_string())];
-            let client = reqwest::Client::new();
-            let _res = client.post(format!("http://{}/shock", QTSHOCK_IP.lock().unwrap()))
-            .form(&params)
-            .send().await
-            .unwrap();
-            Ok(format!("Shock was called with: {}", strength))
-        },
-        _ => {
-            Err("BAD SHOCK CALL".to_string())
-        }
+fn describe_verdict(action: &str, verdict: SafetyVerdict) -> String {
+    match verdict.reason {
+        Some(reason) => format!("{:?} was sent with: {} ({})", verdict.interaction, verdict.strength, reason),
+        None => format!("{} was called with: {}", action, verdict.strength)
     }
-    
 }
 
 #[tauri::command]
-async fn vibrate(shocker: u8, strength: &str) -> Result<String, String> {
-    match strength.to_string().parse::<i16>() {
-        Ok(i) => {
-            if i < 1 || i > 99 {
-                return Err("".to_string());
-            }
-            let params = [("shocker", shocker.to_string()), ("strength", strength.to_string())];
-            let client = reqwest::Client::new();
-            let _res = client.post(format!("http://{}/vibrate", QTSHOCK_IP.lock().unwrap()))
-            .form(&params)
-            .send().await
-            .unwrap();
-            Ok(format!("Vibrate was called with: {}", strength))
-        },
-        _ => {
-            Err("BAD VIBRATE CALL".to_string())
-        }
-    }
+async fn shock(app: AppHandle, target: String, strength: &str, duration: Option<u32>) -> Result<String, String> {
+    let strength = parse_strength(strength)?;
+    let verdict = trigger_qtshock(&app, &target, QTSInteraction::SHOCK, Some(strength), duration, &TriggerScope::default()).await?;
+    Ok(describe_verdict("Shock", verdict))
 }
 
 #[tauri::command]
-async fn beep(shocker: u8) -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let params = [("shocker", shocker.to_string())];
-    let _res = client.post(format!("http://{}/beep", QTSHOCK_IP.lock().unwrap()))
-    .form(&params)
-    .send().await
-    .unwrap();
+async fn vibrate(app: AppHandle, target: String, strength: &str, duration: Option<u32>) -> Result<String, String> {
+    let strength = parse_strength(strength)?;
+    let verdict = trigger_qtshock(&app, &target, QTSInteraction::VIBRATE, Some(strength), duration, &TriggerScope::default()).await?;
+    Ok(describe_verdict("Vibrate", verdict))
+}
+
+#[tauri::command]
+async fn beep(app: AppHandle, target: String, duration: Option<u32>) -> Result<String, String> {
+    trigger_qtshock(&app, &target, QTSInteraction::BEEP, None, duration, &TriggerScope::default()).await?;
     Ok(format!("Beep was called"))
 }
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_store::Builder::default().build())
-        .invoke_handler(tauri::generate_handler![load_local_ip, firmware::firmware::flash_device_firmware, firmware::firmware::factory_reset_device, firmware::firmware::get_available_serial_devices, set_shock_strength, set_vibrate_strength, create_cs_config, start_cs_listener, start_vrc_osc, shock, vibrate, beep])
+        .manage(vrc::VrcService::new())
+        .manage(cs::CsService::new())
+        .setup(|app| {
+            let loaded = settings::load(&app.handle());
+            if loaded.integrations.vrc_enabled {
+                let _ = vrc::set_enabled(&app.handle(), true);
+            }
+            if loaded.integrations.cs_enabled {
+                let _ = block_on(cs::set_enabled(&app.handle(), true));
+            }
+            if let Err(e) = discovery::start_device_discovery(app.handle()) {
+                println!("{}", e);
+            }
+            emergency::register_shortcut(&app.handle());
+            let api_app = app.handle();
+            tauri::async_runtime::spawn(async move {
+                api::api_thread(api_app).await;
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![load_local_ip, firmware::firmware::flash_device_firmware, firmware::firmware::factory_reset_device, firmware::firmware::get_available_serial_devices, set_shock_strength, set_vibrate_strength, cs::create_cs_config, cs::start_cs_listener, vrc::start_vrc_osc, vrc::get_osc_config, vrc::set_osc_config, vrc::get_osc_triggers, vrc::set_osc_triggers, vrc::get_osc_mappings, vrc::save_osc_mapping, vrc::delete_osc_mapping, vrc::get_chatbox_config, vrc::set_chatbox_config, vrc::get_avatar_profiles, vrc::get_current_avatar, vrc::save_avatar_profile, vrc::delete_avatar_profile, vrc::start_osc_recording, vrc::stop_osc_recording, vrc::replay_osc_recording, vrc::stop_osc_replay, cs::set_cs_target, shock, vibrate, beep, devices::get_devices, devices::add_device, devices::remove_device, devices::rename_device, devices::set_device_ip, devices::add_shocker, devices::remove_shocker, devices::rename_shocker, devices::set_shocker_groups, devices::set_shocker_strengths, discovery::start_device_discovery, discovery::stop_device_discovery, discovery::get_discovered_devices, safety::get_safety_limits, safety::set_safety_limits, safety::get_safety_usage, emergency::emergency_stop, emergency::rearm_emergency_stop, emergency::get_emergency_stop, cs::set_cs_death_action, patterns::get_patterns, patterns::save_pattern, patterns::delete_pattern, patterns::play_pattern, patterns::stop_pattern, settings::get_settings, settings::set_settings, status::get_integration_status])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,237 @@
+use rosc::OscType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::client::{MAX_STRENGTH, MIN_STRENGTH};
+use crate::defines::{QTSAction, QTSInteraction};
+use crate::latch::Hysteresis;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MappingCondition {
+    // Bools fire when they turn true
+    BoolTrue,
+    // Floats latch like PUSH parameters
+    Threshold { trigger: Hysteresis },
+    // Floats fire when they move into the range, inclusive
+    Range { min: f32, max: f32 },
+    // Ints fire when they change to the value
+    IntEquals { value: i32 }
+}
+
+// Parameters VRChat sets on every avatar, see its OSC avatar parameter docs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VrcBuiltin {
+    AFK,
+    InStation,
+    IsLocal,
+    Seated,
+    VRMode
+}
+
+impl VrcBuiltin {
+    pub fn from_parameter(parameter: &str) -> Option<Self> {
+        match parameter {
+            "AFK" => Some(VrcBuiltin::AFK),
+            "InStation" => Some(VrcBuiltin::InStation),
+            "IsLocal" => Some(VrcBuiltin::IsLocal),
+            "Seated" => Some(VrcBuiltin::Seated),
+            "VRMode" => Some(VrcBuiltin::VRMode),
+            _ => None
+        }
+    }
+}
+
+// Ordered from mildest to strongest, the strongest active gate wins
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GateEffect {
+    // Shocks become vibrations, patterns can't be changed so they're suppressed
+    Vibrate,
+    // Interactions become beeps, patterns are suppressed
+    Beep,
+    Suppress
+}
+
+// Applies `effect` while the built-in parameter equals `when`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MappingGate {
+    pub parameter: VrcBuiltin,
+    #[serde(default = "default_true")]
+    pub when: bool,
+    pub effect: GateEffect
+}
+
+/*
+    A user-defined trigger for any OSC address, so avatars don't need QTS_ parameter names.
+    `address` may use `*` for any run of characters and `?` for a single character.
+    `gates` hold it back or tone it down depending on VRChat's built-in parameters.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OscMapping {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub address: String,
+    pub condition: MappingCondition,
+    pub action: QTSAction,
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub strength: Option<u8>,
+    // Replaces the chatbox template when this mapping fires, empty to keep it quiet
+    #[serde(default)]
+    pub chatbox: Option<String>,
+    #[serde(default)]
+    pub gates: Vec<MappingGate>
+}
+
+impl OscMapping {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Mapping names can't be empty".to_string());
+        }
+        if !self.address.starts_with('/') {
+            return Err(format!("Mapping '{}' needs an OSC address starting with /", self.name));
+        }
+        if let Some(strength) = self.strength {
+            if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+                return Err(format!("Mapping '{}' has a strength outside {}-{}", self.name, MIN_STRENGTH, MAX_STRENGTH));
+            }
+        }
+        match &self.condition {
+            MappingCondition::Threshold { trigger } => {
+                trigger.validate().map_err(|e| format!("Mapping '{}': {}", self.name, e))?;
+            },
+            MappingCondition::Range { min, max } if min > max => {
+                return Err(format!("Mapping '{}' has a range that ends before it starts", self.name));
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, address: &str) -> bool {
+        self.enabled && wildcard_match(&self.address, address)
+    }
+
+    // What to fire given the built-ins right now, or why nothing should fire
+    pub fn gated_action(&self, builtins: &Builtins) -> Result<(QTSAction, Option<u8>), String> {
+        let active = self.gates.iter()
+            .filter(|gate| builtins.get(gate.parameter) == gate.when)
+            .max_by_key(|gate| gate.effect);
+        let gate = match active {
+            Some(gate) => gate,
+            None => {
+                return Ok((self.action.clone(), self.strength));
+            }
+        };
+        let suppressed = Err(format!("Mapping '{}' held back while {:?} is {}", self.name, gate.parameter, gate.when));
+        match (gate.effect, &self.action) {
+            (GateEffect::Suppress, _) | (_, QTSAction::Pattern(_)) => suppressed,
+            (GateEffect::Vibrate, QTSAction::Interaction(QTSInteraction::SHOCK)) => Ok((QTSAction::Interaction(QTSInteraction::VIBRATE), None)),
+            (GateEffect::Vibrate, action) => Ok((action.clone(), self.strength)),
+            (GateEffect::Beep, _) => Ok((QTSAction::Interaction(QTSInteraction::BEEP), None))
+        }
+    }
+
+    // Latches and edge state are kept per mapping and address, a wildcard can cover several parameters
+    pub fn state_key(&self, address: &str) -> String {
+        format!("{}|{}", self.name, address)
+    }
+}
+
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was and how much text it has swallowed so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub enum EdgeResult {
+    Fire,
+    Nothing,
+    BadValue
+}
+
+// Remembers whether each edge-triggered condition held last time so it only fires on the change
+pub struct MappingEdges {
+    matched: Mutex<HashMap<String, bool>>
+}
+
+impl MappingEdges {
+    pub fn new() -> Self {
+        Self { matched: Mutex::new(HashMap::new()) }
+    }
+
+    // Evaluates the conditions that aren't latched, Threshold is handled with the PUSH latches
+    pub fn update(&self, key: &str, condition: &MappingCondition, value: &OscType) -> EdgeResult {
+        let now = match (condition, value) {
+            (MappingCondition::BoolTrue, OscType::Bool(b)) => *b,
+            (MappingCondition::Range { min, max }, OscType::Float(f)) => (*min..=*max).contains(f),
+            (MappingCondition::IntEquals { value: expected }, OscType::Int(i)) => i == expected,
+            _ => {
+                return EdgeResult::BadValue;
+            }
+        };
+        let before = self.matched.lock().unwrap().insert(key.to_string(), now).unwrap_or(false);
+        if now && !before {
+            return EdgeResult::Fire;
+        }
+        EdgeResult::Nothing
+    }
+
+    pub fn clear(&self) {
+        self.matched.lock().unwrap().clear();
+    }
+}
+
+// The latest built-in values, anything VRChat hasn't sent yet counts as false
+pub struct Builtins {
+    values: Mutex<HashMap<VrcBuiltin, bool>>
+}
+
+impl Builtins {
+    pub fn new() -> Self {
+        Self { values: Mutex::new(HashMap::new()) }
+    }
+
+    // VRMode is an int, the rest are bools
+    pub fn update(&self, builtin: VrcBuiltin, value: &OscType) {
+        let value = match value {
+            OscType::Bool(b) => *b,
+            OscType::Int(i) => *i != 0,
+            _ => {
+                return;
+            }
+        };
+        self.values.lock().unwrap().insert(builtin, value);
+    }
+
+    pub fn get(&self, builtin: VrcBuiltin) -> bool {
+        *self.values.lock().unwrap().get(&builtin).unwrap_or(&false)
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::client::{MAX_STRENGTH, MIN_STRENGTH};
+use crate::defines::{QTSAction, QTSInteraction, QTSOSCType};
+
+const QTS_MARKER: &str = "QTS_";
+const PATTERN_KEYWORD: &str = "PATTERN";
+// QTHub's own parameters, they share the prefix but aren't triggers
+pub const RESERVED_TARGETS: [&str; 3] = ["IN", "OUT", "PANIC"];
+
+/*
+    Avatar parameters that trigger the QTShock follow this grammar, matched against the last
+    path segment of the OSC address:
+
+        parameter := [ anything "_" ] "QTS_" target "_" type "_" action
+        target    := shocker index (0-255) | device, shocker or group name (no "_")
+        type      := "PUSH" | "HIT" | "PROP"
+        action    := interaction [ "_" strength ]
+                   | "PATTERN_" pattern name (may contain "_")
+                   | pattern name (single word, kept for older avatars)
+        interaction := "SHOCK" | "VIBRATE" | "BEEP"
+        strength  := 1-99, the maximum strength for PROP
+
+    e.g. QTS_0_PUSH_SHOCK, My_QTS_Collar_HIT_VIBRATE_40, QTS_legs_HIT_PATTERN_slow_tease
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct QtsAddress {
+    pub target: String,
+    pub osc_type: QTSOSCType,
+    pub action: QTSAction,
+    pub strength: Option<u8>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QtsAddressError {
+    NotQts,
+    Reserved(String),
+    MissingTarget,
+    BadTarget(String),
+    MissingType,
+    BadType(String),
+    MissingAction,
+    BadStrength(String)
+}
+
+impl fmt::Display for QtsAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QtsAddressError::NotQts => write!(f, "Not a QTShock parameter"),
+            QtsAddressError::Reserved(name) => write!(f, "QTS_{} is one of QTHub's own parameters", name),
+            QtsAddressError::MissingTarget => write!(f, "Missing shocker after QTS_"),
+            QtsAddressError::BadTarget(target) => write!(f, "'{}' isn't a shocker index or name", target),
+            QtsAddressError::MissingType => write!(f, "Missing PUSH, HIT or PROP after the shocker"),
+            QtsAddressError::BadType(osc_type) => write!(f, "Bad command type '{}', expected PUSH, HIT or PROP", osc_type),
+            QtsAddressError::MissingAction => write!(f, "Missing interaction or pattern"),
+            QtsAddressError::BadStrength(strength) => write!(f, "Bad strength '{}', expected {}-{}", strength, MIN_STRENGTH, MAX_STRENGTH)
+        }
+    }
+}
+
+impl std::error::Error for QtsAddressError {}
+
+// Whether a device, shocker or group name fits `target` in the grammar above
+pub fn is_target_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn parse_target(target: &str) -> Result<String, QtsAddressError> {
+    if target.is_empty() {
+        return Err(QtsAddressError::MissingTarget);
+    }
+    if target.chars().all(|c| c.is_ascii_digit()) {
+        // Anything bigger can't be a shocker, don't let it fall through to a name lookup
+        return match target.parse::<u8>() {
+            Ok(index) => Ok(index.to_string()),
+            Err(_) => Err(QtsAddressError::BadTarget(target.to_string()))
+        };
+    }
+    if !is_target_name(target) {
+        return Err(QtsAddressError::BadTarget(target.to_string()));
+    }
+    Ok(target.to_string())
+}
+
+fn parse_strength(strength: &str) -> Result<u8, QtsAddressError> {
+    match strength.parse::<u8>() {
+        Ok(value) if (MIN_STRENGTH..=MAX_STRENGTH).contains(&value) => Ok(value),
+        _ => Err(QtsAddressError::BadStrength(strength.to_string()))
+    }
+}
+
+// Finds "QTS_" at the start of the parameter or right after an "_", so "MyQTS_" doesn't count
+fn find_marker(parameter: &str) -> Option<usize> {
+    parameter.match_indices(QTS_MARKER)
+        .map(|(i, _)| i)
+        .find(|&i| i == 0 || parameter[..i].ends_with('_'))
+}
+
+pub fn parse_qts_address(address: &str) -> Result<QtsAddress, QtsAddressError> {
+    let parameter = address.rsplit('/').next().unwrap_or(address);
+    let start = match find_marker(parameter) {
+        Some(start) => start + QTS_MARKER.len(),
+        None => {
+            return Err(QtsAddressError::NotQts);
+        }
+    };
+    let mut parts = parameter[start..].splitn(3, '_');
+
+    let target = parts.next().unwrap_or("");
+    if RESERVED_TARGETS.contains(&target) {
+        return Err(QtsAddressError::Reserved(parameter[start..].to_string()));
+    }
+    let target = parse_target(target)?;
+
+    let osc_type = match parts.next() {
+        Some(osc_type) if !osc_type.is_empty() => osc_type,
+        _ => {
+            return Err(QtsAddressError::MissingType);
+        }
+    };
+    let osc_type = match QTSOSCType::from_str(osc_type) {
+        Ok(osc_type) => osc_type,
+        Err(_) => {
+            return Err(QtsAddressError::BadType(osc_type.to_string()));
+        }
+    };
+
+    let action = match parts.next() {
+        Some(action) if !action.is_empty() => action,
+        _ => {
+            return Err(QtsAddressError::MissingAction);
+        }
+    };
+    let (head, rest) = match action.split_once('_') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (action, None)
+    };
+
+    if let Ok(interaction) = QTSInteraction::from_str(head) {
+        let strength = match rest {
+            Some(strength) => Some(parse_strength(strength)?),
+            None => None
+        };
+        return Ok(QtsAddress { target, osc_type, action: QTSAction::Interaction(interaction), strength });
+    }
+    let pattern = match (head, rest) {
+        (PATTERN_KEYWORD, Some(name)) if !name.is_empty() => name,
+        (PATTERN_KEYWORD, _) => {
+            return Err(QtsAddressError::MissingAction);
+        },
+        _ => action
+    };
+    Ok(QtsAddress { target, osc_type, action: QTSAction::Pattern(pattern.to_string()), strength: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(parameter: &str) -> Result<QtsAddress, QtsAddressError> {
+        parse_qts_address(&format!("/avatar/parameters/{}", parameter))
+    }
+
+    #[test]
+    fn parses_an_index_target() {
+        assert_eq!(parse("QTS_0_PUSH_SHOCK"), Ok(QtsAddress {
+            target: "0".to_string(),
+            osc_type: QTSOSCType::PUSH,
+            action: QTSAction::Interaction(QTSInteraction::SHOCK),
+            strength: None
+        }));
+    }
+
+    #[test]
+    fn parses_a_prefixed_named_target_with_strength() {
+        assert_eq!(parse("My_QTS_Collar_HIT_VIBRATE_40"), Ok(QtsAddress {
+            target: "Collar".to_string(),
+            osc_type: QTSOSCType::HIT,
+            action: QTSAction::Interaction(QTSInteraction::VIBRATE),
+            strength: Some(40)
+        }));
+    }
+
+    #[test]
+    fn parses_a_pattern_name_with_underscores() {
+        assert_eq!(parse("QTS_legs_HIT_PATTERN_slow_tease"), Ok(QtsAddress {
+            target: "legs".to_string(),
+            osc_type: QTSOSCType::HIT,
+            action: QTSAction::Pattern("slow_tease".to_string()),
+            strength: None
+        }));
+    }
+
+    #[test]
+    fn parses_a_bare_pattern_name() {
+        assert_eq!(parse("QTS_0_HIT_tease").map(|address| address.action), Ok(QTSAction::Pattern("tease".to_string())));
+    }
+
+    #[test]
+    fn parses_strength_bounds() {
+        assert_eq!(parse(&format!("QTS_0_PROP_SHOCK_{}", MIN_STRENGTH)).map(|address| address.strength), Ok(Some(MIN_STRENGTH)));
+        assert_eq!(parse(&format!("QTS_0_PROP_SHOCK_{}", MAX_STRENGTH)).map(|address| address.strength), Ok(Some(MAX_STRENGTH)));
+    }
+
+    #[test]
+    fn ignores_a_marker_inside_a_word() {
+        assert_eq!(parse("MyQTS_0_HIT_SHOCK"), Err(QtsAddressError::NotQts));
+        assert_eq!(parse("SomethingElse"), Err(QtsAddressError::NotQts));
+    }
+
+    #[test]
+    fn rejects_an_index_past_255() {
+        assert_eq!(parse("QTS_256_HIT_SHOCK"), Err(QtsAddressError::BadTarget("256".to_string())));
+    }
+
+    #[test]
+    fn rejects_missing_parts() {
+        assert_eq!(parse("QTS_"), Err(QtsAddressError::MissingTarget));
+        assert_eq!(parse("QTS_0"), Err(QtsAddressError::MissingType));
+        assert_eq!(parse("QTS_0_HIT"), Err(QtsAddressError::MissingAction));
+        assert_eq!(parse("QTS_0_HIT_PATTERN_"), Err(QtsAddressError::MissingAction));
+    }
+
+    #[test]
+    fn rejects_an_unknown_type() {
+        assert_eq!(parse("QTS_0_FOO_SHOCK"), Err(QtsAddressError::BadType("FOO".to_string())));
+    }
+
+    #[test]
+    fn rejects_strengths_out_of_range() {
+        assert_eq!(parse("QTS_0_HIT_SHOCK_0"), Err(QtsAddressError::BadStrength("0".to_string())));
+        assert_eq!(parse("QTS_0_HIT_SHOCK_100"), Err(QtsAddressError::BadStrength("100".to_string())));
+        assert_eq!(parse("QTS_0_HIT_SHOCK_lots"), Err(QtsAddressError::BadStrength("lots".to_string())));
+    }
+
+    #[test]
+    fn reserves_qthubs_own_parameters() {
+        assert_eq!(parse("QTS_IN_SHOCK_STRENGTH"), Err(QtsAddressError::Reserved("IN_SHOCK_STRENGTH".to_string())));
+        assert_eq!(parse("QTS_OUT_CONNECTED"), Err(QtsAddressError::Reserved("OUT_CONNECTED".to_string())));
+        assert_eq!(parse("QTS_OUT_0_STRENGTH"), Err(QtsAddressError::Reserved("OUT_0_STRENGTH".to_string())));
+        assert_eq!(parse("QTS_PANIC"), Err(QtsAddressError::Reserved("PANIC".to_string())));
+    }
+
+    #[test]
+    fn target_names_match_what_parses() {
+        for name in ["Collar", "left-leg", "QT2"] {
+            assert!(is_target_name(name));
+            assert_eq!(parse(&format!("QTS_{}_HIT_BEEP", name)).map(|address| address.target), Ok(name.to_string()));
+        }
+        for name in ["", "left_leg", "left leg", "a/b", "é"] {
+            assert!(!is_target_name(name), "'{}' was allowed", name);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// Rejected senders are reported again after this, packets_dropped keeps the totals
+const REPORT_WINDOW: Duration = Duration::from_secs(60);
+// Spoofed sources could otherwise grow these without end
+const MAX_TRACKED: usize = 256;
+
+
+// An IP address or subnet in CIDR notation, e.g. "192.168.1.20" or "192.168.1.0/24"
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceRule {
+    network: IpAddr,
+    prefix: u8
+}
+
+impl FromStr for SourceRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim();
+        let (addr, prefix) = match rule.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (rule, None)
+        };
+        let network = IpAddr::from_str(addr).map_err(|_| format!("'{}' isn't an IP address or subnet", rule))?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max_prefix => prefix,
+                _ => {
+                    return Err(format!("'{}' has a bad subnet size", rule));
+                }
+            },
+            None => max_prefix
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl SourceRule {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Dual-stack sockets report IPv4 senders as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            },
+            _ => false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rejection {
+    NotAllowed,
+    RateLimited
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::NotAllowed => write!(f, "isn't on the OSC allow-list"),
+            Rejection::RateLimited => write!(f, "is sending too many OSC packets")
+        }
+    }
+}
+
+/*
+    Decides which senders the VRChat receiver listens to. Owned by the receiver thread,
+    a config change restarts the integration and builds a new one.
+*/
+pub struct SourceFilter {
+    rules: Vec<SourceRule>,
+    // 0 turns rate limiting off
+    max_per_second: u32,
+    windows: HashMap<IpAddr, (Instant, u32)>,
+    reported: HashSet<(IpAddr, Rejection)>,
+    reported_since: Instant
+}
+
+impl SourceFilter {
+    pub fn new(allowed: &[String], max_per_second: u32) -> Result<Self, String> {
+        let rules = allowed.iter().map(|rule| SourceRule::from_str(rule)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { rules, max_per_second, windows: HashMap::new(), reported: HashSet::new(), reported_since: Instant::now() })
+    }
+
+    pub fn check(&mut self, ip: IpAddr) -> Result<(), Rejection> {
+        if !self.rules.iter().any(|rule| rule.contains(ip)) {
+            return Err(Rejection::NotAllowed);
+        }
+        if self.max_per_second == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if self.windows.len() >= MAX_TRACKED && !self.windows.contains_key(&ip) {
+            self.windows.retain(|_, window| now.duration_since(window.0) < RATE_WINDOW);
+        }
+        let window = self.windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(window.0) >= RATE_WINDOW {
+            *window = (now, 0);
+        }
+        window.1 += 1;
+        if window.1 > self.max_per_second {
+            return Err(Rejection::RateLimited);
+        }
+        Ok(())
+    }
+
+    /*
+        True the first time a sender is rejected for a reason each minute, so the console isn't
+        flooded. Once too many senders have been reported the rest stay quiet until the reset.
+    */
+    pub fn first_rejection(&mut self, ip: IpAddr, rejection: Rejection) -> bool {
+        if self.reported_since.elapsed() >= REPORT_WINDOW {
+            self.reported.clear();
+            self.reported_since = Instant::now();
+        }
+        if self.reported.len() >= MAX_TRACKED {
+            return false;
+        }
+        self.reported.insert((ip, rejection))
+    }
+}
@@ -0,0 +1,214 @@
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use poem::{
+    get, handler, listener::{Acceptor, Listener, TcpListener},
+    web::{Data, Json}, EndpointExt, Request, Route, Server
+};
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::thread;
+use std::time::Duration;
+use tauri::async_runtime::block_on;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::vrc::VrcService;
+use crate::{feedback, Payload};
+
+// Apps advertise their OSCQuery HTTP server and their OSC port separately
+const OSCJSON_SERVICE: &str = "_oscjson._tcp.local.";
+const OSC_SERVICE: &str = "_osc._udp.local.";
+const VRCHAT_INSTANCE_PREFIX: &str = "VRChat-Client-";
+const QTHUB_HOSTNAME: &str = "qthub.local.";
+
+// Parameters QTHub reads or writes, VRChat sends us every avatar parameter once /avatar is listed
+const ADVERTISED_PARAMETERS: [(&str, &str, u8); 7] = [
+    ("QTS_PANIC", "T", 2),
+    (feedback::CONNECTED_PARAMETER, "T", 1),
+    (feedback::COOLDOWN_PARAMETER, "T", 1),
+    (feedback::LOCKED_PARAMETER, "T", 1),
+    (feedback::LAST_INTERACTION_PARAMETER, "i", 1),
+    ("QTS_IN_SHOCK_STRENGTH", "f", 3),
+    ("QTS_IN_VIBRATE_STRENGTH", "f", 3)
+];
+
+
+#[derive(Clone)]
+struct HostInfo {
+    name: String,
+    osc: SocketAddr
+}
+
+/*
+    OSCQuery lets several OSC apps share VRChat. We receive on an ephemeral UDP port, describe
+    it over HTTP and advertise both over mDNS, and find VRChat's own OSC port the same way.
+*/
+pub struct OscQuery {
+    daemon: ServiceDaemon,
+    cancel: CancellationToken,
+    pub http_addr: SocketAddr
+}
+
+fn host_info_json(info: &HostInfo) -> JsonValue {
+    json!({
+        "NAME": info.name,
+        "OSC_IP": info.osc.ip().to_string(),
+        "OSC_PORT": info.osc.port(),
+        "OSC_TRANSPORT": "UDP",
+        "EXTENSIONS": {
+            "ACCESS": true,
+            "CLIPMODE": false,
+            "RANGE": false,
+            "TYPE": true,
+            "VALUE": false
+        }
+    })
+}
+
+fn node_tree_json() -> JsonValue {
+    let mut parameters = serde_json::Map::new();
+    for (name, osc_type, access) in ADVERTISED_PARAMETERS {
+        parameters.insert(name.to_string(), json!({
+            "FULL_PATH": format!("/avatar/parameters/{}", name),
+            "TYPE": osc_type,
+            "ACCESS": access
+        }));
+    }
+    json!({
+        "FULL_PATH": "/",
+        "ACCESS": 0,
+        "CONTENTS": {
+            "avatar": {
+                "FULL_PATH": "/avatar",
+                "ACCESS": 0,
+                "CONTENTS": {
+                    "change": {
+                        "FULL_PATH": "/avatar/change",
+                        "TYPE": "s",
+                        "ACCESS": 2
+                    },
+                    "parameters": {
+                        "FULL_PATH": "/avatar/parameters",
+                        "ACCESS": 0,
+                        "CONTENTS": parameters
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[handler]
+async fn oscquery_root(req: &Request, info: Data<&HostInfo>) -> Json<JsonValue> {
+    match req.uri().query() {
+        Some(query) if query.starts_with("HOST_INFO") => Json(host_info_json(&info)),
+        _ => Json(node_tree_json())
+    }
+}
+
+fn browse_vrchat(app: AppHandle, daemon: ServiceDaemon) {
+    let receiver = match daemon.browse(OSC_SERVICE) {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            println!("Failed to browse for VRChat: {}", e);
+            return;
+        }
+    };
+    let mut vrchat_fullname: Option<String> = None;
+
+    while let Ok(event) = receiver.recv() {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                if !info.get_fullname().starts_with(VRCHAT_INSTANCE_PREFIX) {
+                    continue;
+                }
+                // VRChat advertises every interface, loopback is the one to use when it's local
+                let addresses = info.get_addresses_v4();
+                let ip = match addresses.iter().find(|ip| ip.is_loopback()).or(addresses.iter().next()) {
+                    Some(ip) => IpAddr::V4(**ip),
+                    None => {
+                        continue;
+                    }
+                };
+                let addr = SocketAddr::new(ip, info.get_port());
+                println!("Found VRChat OSC at {}", addr);
+                let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Found VRChat at {}", addr) });
+                vrchat_fullname = Some(info.get_fullname().to_string());
+                app.state::<VrcService>().set_vrchat_addr(Some(addr));
+            },
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                if vrchat_fullname.as_deref() == Some(fullname.as_str()) {
+                    println!("VRChat OSC went away");
+                    vrchat_fullname = None;
+                    app.state::<VrcService>().set_vrchat_addr(None);
+                }
+            },
+            ServiceEvent::SearchStopped(_) => {
+                break;
+            },
+            _ => {}
+        }
+    }
+    println!("Stopped browsing for VRChat");
+}
+
+impl OscQuery {
+    // `osc_addr` is the bound receive socket, `cancel` is shared with the VRC receiver
+    pub fn start(app: &AppHandle, osc_addr: SocketAddr, cancel: CancellationToken) -> Result<Self, String> {
+        let http_bind = SocketAddr::new(osc_addr.ip(), 0);
+        let acceptor = block_on(TcpListener::bind(http_bind.to_string()).into_acceptor())
+            .map_err(|e| format!("Couldn't start the OSCQuery server: {}", e))?;
+        let http_addr = match acceptor.local_addr().first().and_then(|addr| addr.as_socket_addr().cloned()) {
+            Some(addr) => addr,
+            None => {
+                return Err("Couldn't find the OSCQuery server's port".to_string());
+            }
+        };
+
+        let name = format!("QTHub-{}", std::process::id());
+        let daemon = ServiceDaemon::new().map_err(|e| format!("Couldn't start mDNS for OSCQuery: {}", e))?;
+        for (service_type, port) in [(OSCJSON_SERVICE, http_addr.port()), (OSC_SERVICE, osc_addr.port())] {
+            let registered = ServiceInfo::new(service_type, &name, QTHUB_HOSTNAME, (), port, HashMap::<String, String>::new())
+                .map(|service| service.enable_addr_auto())
+                .and_then(|service| daemon.register(service));
+            if let Err(e) = registered {
+                let _ = daemon.shutdown();
+                return Err(format!("Couldn't advertise the OSCQuery service: {}", e));
+            }
+        }
+        let browse_app = app.clone();
+        let browse_daemon = daemon.clone();
+        thread::spawn(move || {
+            browse_vrchat(browse_app, browse_daemon);
+        });
+
+        // Clients on this machine can't send to the unspecified address
+        let osc_ip = if osc_addr.ip().is_unspecified() { IpAddr::V4(Ipv4Addr::LOCALHOST) } else { osc_addr.ip() };
+        let host_info = HostInfo { name: name.clone(), osc: SocketAddr::new(osc_ip, osc_addr.port()) };
+        let shutdown = cancel.clone();
+        tauri::async_runtime::spawn(async move {
+            let routes = Route::new().at("/", get(oscquery_root)).data(host_info);
+            let res = Server::new_with_acceptor(acceptor)
+                .run_with_graceful_shutdown(routes,
+                async move {
+                    shutdown.cancelled().await
+                },
+                Some(Duration::from_secs(1)))
+                .await;
+            if let Err(e) = res {
+                println!("OSCQuery server stopped: {}", e);
+            }
+        });
+
+        println!("OSCQuery advertised as {} on {}", name, http_addr);
+        Ok(Self { daemon, cancel, http_addr })
+    }
+
+    pub fn stop(self) {
+        self.cancel.cancel();
+        let _ = self.daemon.stop_browse(OSC_SERVICE);
+        if let Err(e) = self.daemon.shutdown() {
+            println!("Failed to stop OSCQuery advertising: {}", e);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::client::{DeviceError, MAX_STRENGTH, MIN_STRENGTH};
+use crate::defines::QTSInteraction;
+use crate::devices;
+use crate::safety::MAX_DURATION_MS;
+use crate::TriggerScope;
+
+// Anything longer is a typo, not a pattern anyone wants to sit through
+const MAX_REPEAT: u16 = 100;
+const MAX_PAUSE_MS: u32 = 60_000;
+
+fn default_repeat() -> u16 {
+    1
+}
+
+/*
+    One step of a pattern. The step fires `repeat` pulses, each lasting `duration_ms`
+    followed by `pause_ms`. With `ramp_to` set the strength moves linearly from
+    `strength` to `ramp_to` over the repeats, jitter is applied on top of that.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PatternStep {
+    pub interaction: QTSInteraction,
+    #[serde(default)]
+    pub strength: Option<u8>,
+    #[serde(default)]
+    pub ramp_to: Option<u8>,
+    #[serde(default)]
+    pub duration_ms: u32,
+    #[serde(default)]
+    pub pause_ms: u32,
+    #[serde(default = "default_repeat")]
+    pub repeat: u16,
+    #[serde(default)]
+    pub strength_jitter: u8,
+    #[serde(default)]
+    pub pause_jitter_ms: u32
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Pattern {
+    pub name: String,
+    pub steps: Vec<PatternStep>
+}
+
+impl Pattern {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Pattern names can't be empty".to_string());
+        }
+        if self.steps.is_empty() {
+            return Err(format!("Pattern '{}' has no steps", self.name));
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            for strength in [step.strength, step.ramp_to].into_iter().flatten() {
+                if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+                    return Err(format!("Step {} of '{}' has a strength outside {}-{}", i + 1, self.name, MIN_STRENGTH, MAX_STRENGTH));
+                }
+            }
+            if step.ramp_to.is_some() && step.strength.is_none() {
+                return Err(format!("Step {} of '{}' ramps without a starting strength", i + 1, self.name));
+            }
+            if step.repeat == 0 || step.repeat > MAX_REPEAT {
+                return Err(format!("Step {} of '{}' must repeat between 1 and {} times", i + 1, self.name, MAX_REPEAT));
+            }
+            if step.duration_ms > MAX_DURATION_MS {
+                return Err(format!("Step {} of '{}' can last at most {}ms", i + 1, self.name, MAX_DURATION_MS));
+            }
+            if step.pause_ms > MAX_PAUSE_MS || step.pause_jitter_ms > MAX_PAUSE_MS {
+                return Err(format!("Step {} of '{}' can pause for at most {}ms", i + 1, self.name, MAX_PAUSE_MS));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PatternStep {
+    fn strength_at(&self, repetition: u16) -> Option<u8> {
+        let start = self.strength?;
+        let mut strength = match self.ramp_to {
+            Some(end) if self.repeat > 1 => {
+                let progress = repetition as f32 / (self.repeat - 1) as f32;
+                start as f32 + (end as f32 - start as f32) * progress
+            },
+            _ => start as f32
+        };
+        if self.strength_jitter > 0 {
+            let jitter = self.strength_jitter as f32;
+            strength += rand::thread_rng().gen_range(-jitter..=jitter);
+        }
+        Some(strength.round().clamp(MIN_STRENGTH as f32, MAX_STRENGTH as f32) as u8)
+    }
+
+    fn wait_after(&self) -> Duration {
+        let mut pause = self.pause_ms as u64;
+        if self.pause_jitter_ms > 0 {
+            pause += rand::thread_rng().gen_range(0..=self.pause_jitter_ms as u64);
+        }
+        Duration::from_millis(self.duration_ms as u64 + pause)
+    }
+}
+
+
+pub static QTSHOCK_PATTERNS: Mutex<Vec<Pattern>> = Mutex::new(Vec::new());
+
+// A device name and shocker index, runs are keyed by what a target resolves to rather than how it's written
+type ShockerKey = (String, u8);
+
+// The currently running pattern per shocker, a newer run on any of a run's shockers preempts it
+static PATTERN_RUNS: Mutex<BTreeMap<ShockerKey, u64>> = Mutex::new(BTreeMap::new());
+static PATTERN_RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+
+pub fn find_pattern(name: &str) -> Option<Pattern> {
+    QTSHOCK_PATTERNS.lock().unwrap().iter().find(|p| p.name.eq_ignore_ascii_case(name)).cloned()
+}
+
+fn shocker_keys(target: &str) -> Result<Vec<ShockerKey>, DeviceError> {
+    Ok(devices::resolve_target(target)?.into_iter().map(|shocker| (shocker.device.to_lowercase(), shocker.shocker)).collect())
+}
+
+// A run keeps going only while it still has all of its shockers
+fn is_current(keys: &[ShockerKey], run: u64, scope: &TriggerScope) -> bool {
+    match &scope.dry_run {
+        Some(dry_run) => !dry_run.is_over(),
+        None => {
+            let runs = PATTERN_RUNS.lock().unwrap();
+            keys.iter().all(|key| runs.get(key) == Some(&run))
+        }
+    }
+}
+
+pub fn cancel_all() {
+    PATTERN_RUNS.lock().unwrap().clear();
+}
+
+async fn run_pattern(app: AppHandle, target: String, keys: Vec<ShockerKey>, pattern: Pattern, run: u64, scope: TriggerScope) {
+    'steps: for step in pattern.steps.iter() {
+        for repetition in 0..step.repeat {
+            if !is_current(&keys, run, &scope) {
+                println!("Pattern '{}' on '{}' was preempted", pattern.name, target);
+                return;
+            }
+            let duration = if step.duration_ms > 0 { Some(step.duration_ms) } else { None };
+            match crate::trigger_qtshock(&app, &target, step.interaction, step.strength_at(repetition), duration, &scope).await {
+                Ok(_) => {},
+                Err(e @ (DeviceError::Blocked(_) | DeviceError::EmergencyStop)) => {
+                    println!("Pattern '{}' stopped: {}", pattern.name, e);
+                    break 'steps;
+                },
+                Err(e) => {
+                    println!("Pattern '{}' step failed: {}", pattern.name, e);
+                }
+            }
+            tokio::time::sleep(step.wait_after()).await;
+        }
+    }
+    PATTERN_RUNS.lock().unwrap().retain(|_, owner| *owner != run);
+}
+
+/*
+    Starts a pattern in the background, stopping any pattern already running on one of the
+    target's shockers, however that pattern's target was written.
+    Each step is capped like a single interaction by the avatar that triggered it. A dry run's
+    patterns stop with the dry run and leave the real ones alone.
+*/
+pub fn start_pattern(app: &AppHandle, target: &str, name: &str, scope: TriggerScope) -> Result<(), DeviceError> {
+    let pattern = match find_pattern(name) {
+        Some(pattern) => pattern,
+        None => {
+            return Err(DeviceError::UnknownPattern(name.to_string()));
+        }
+    };
+    let keys = shocker_keys(target)?;
+    let run = PATTERN_RUN_COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
+    if scope.dry_run.is_none() {
+        let mut runs = PATTERN_RUNS.lock().unwrap();
+        for key in keys.iter() {
+            runs.insert(key.clone(), run);
+        }
+    }
+    let app = app.clone();
+    let target = target.to_string();
+    tauri::async_runtime::spawn(async move {
+        run_pattern(app, target, keys, pattern, run, scope).await;
+    });
+    Ok(())
+}
+
+
+#[tauri::command]
+pub fn get_patterns() -> Vec<Pattern> {
+    QTSHOCK_PATTERNS.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn save_pattern(pattern: Pattern) -> Result<Vec<Pattern>, String> {
+    pattern.validate()?;
+    let mut patterns = QTSHOCK_PATTERNS.lock().unwrap();
+    patterns.retain(|p| !p.name.eq_ignore_ascii_case(&pattern.name));
+    patterns.push(pattern);
+    crate::settings::changed();
+    Ok(patterns.clone())
+}
+
+#[tauri::command]
+pub fn delete_pattern(name: String) -> Result<Vec<Pattern>, String> {
+    let mut patterns = QTSHOCK_PATTERNS.lock().unwrap();
+    let count = patterns.len();
+    patterns.retain(|p| !p.name.eq_ignore_ascii_case(&name));
+    if patterns.len() == count {
+        return Err(format!("No pattern named '{}'", name));
+    }
+    crate::settings::changed();
+    Ok(patterns.clone())
+}
+
+#[tauri::command]
+pub fn play_pattern(app: AppHandle, target: String, name: String) -> Result<(), String> {
+    crate::emergency::check()?;
+    start_pattern(&app, &target, &name, TriggerScope::default())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_pattern(target: Option<String>) {
+    match target {
+        Some(target) => {
+            // Nothing can be playing on a target that doesn't resolve
+            if let Ok(keys) = shocker_keys(&target) {
+                let mut runs = PATTERN_RUNS.lock().unwrap();
+                for key in keys.iter() {
+                    runs.remove(key);
+                }
+            }
+        },
+        None => cancel_all()
+    }
+}
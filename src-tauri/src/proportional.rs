@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::client::{MAX_STRENGTH, MIN_STRENGTH};
+use crate::latch::Hysteresis;
+
+// Continuous mode can't resend faster than the device handles requests
+const MIN_INTERVAL_MS: u32 = 100;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum StrengthCurve {
+    Linear,
+    // Above 1 stays gentle until the value gets close to the top
+    Exponential { exponent: f32 },
+    Stepped { steps: u8 }
+}
+
+impl StrengthCurve {
+    fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            StrengthCurve::Linear => x,
+            StrengthCurve::Exponential { exponent } => x.powf(*exponent),
+            StrengthCurve::Stepped { steps } => (x * *steps as f32).ceil() / *steps as f32
+        }
+    }
+}
+
+/*
+    How a PROP parameter maps onto strength. Once `trigger` latches, values between its
+    threshold and 1 are run through the curve onto `min_strength`..`max_strength`, the safety
+    limits still cap the result. With `continuous` the interaction repeats every `interval_ms`
+    while the value is held.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Proportional {
+    pub trigger: Hysteresis,
+    pub curve: StrengthCurve,
+    pub min_strength: u8,
+    pub max_strength: u8,
+    pub continuous: bool,
+    pub interval_ms: u32
+}
+
+impl Default for Proportional {
+    fn default() -> Self {
+        // Proximity contacts start near 0 so trigger much earlier than PUSH does, the short
+        // hold lets the value settle before a one-off interaction picks its strength
+        let trigger = Hysteresis { on_threshold: 0.1, off_threshold: 0.05, min_hold_ms: 150, ..Hysteresis::default() };
+        Self { trigger, curve: StrengthCurve::Linear, min_strength: MIN_STRENGTH, max_strength: 50, continuous: false, interval_ms: 500 }
+    }
+}
+
+impl Proportional {
+    pub fn validate(&self) -> Result<(), String> {
+        self.trigger.validate()?;
+        for strength in [self.min_strength, self.max_strength] {
+            if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+                return Err(format!("Proportional strengths must be between {} and {}", MIN_STRENGTH, MAX_STRENGTH));
+            }
+        }
+        if self.min_strength > self.max_strength {
+            return Err("The minimum proportional strength is above the maximum".to_string());
+        }
+        match self.curve {
+            StrengthCurve::Exponential { exponent } if !(exponent > 0.0) => {
+                return Err("The curve exponent must be above 0".to_string());
+            },
+            StrengthCurve::Stepped { steps: 0 } => {
+                return Err("A stepped curve needs at least one step".to_string());
+            },
+            _ => {}
+        }
+        if self.continuous && self.interval_ms < MIN_INTERVAL_MS {
+            return Err(format!("The continuous interval must be at least {}ms", MIN_INTERVAL_MS));
+        }
+        Ok(())
+    }
+
+    pub fn strength_for(&self, value: f32) -> u8 {
+        let span = 1.0 - self.trigger.on_threshold;
+        let x = if span > 0.0 { (value - self.trigger.on_threshold) / span } else { 1.0 };
+        let range = (self.max_strength - self.min_strength) as f32;
+        let strength = self.min_strength as f32 + range * self.curve.apply(x);
+        // NaN survives the clamp and would cast to 0
+        if strength.is_nan() {
+            return self.min_strength;
+        }
+        strength.round().clamp(self.min_strength as f32, self.max_strength as f32) as u8
+    }
+}
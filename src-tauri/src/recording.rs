@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::defines::QTSInteraction;
+use crate::devices::ShockerTarget;
+use crate::safety::{SafetyLimiter, QTSHOCK_SAFETY};
+use crate::vrc::{self, OscContext, VrcService};
+use crate::Payload;
+
+/*
+    Where a dry-run replay's device requests go instead of the devices. It brings its own copy
+    of the safety limits so testing doesn't use up the real ones, and ends with the replay.
+*/
+#[derive(Clone)]
+pub struct DryRun {
+    app: AppHandle,
+    safety: Arc<Mutex<SafetyLimiter>>,
+    cancel: CancellationToken
+}
+
+impl DryRun {
+    fn new(app: &AppHandle, cancel: CancellationToken) -> Self {
+        let mut safety = SafetyLimiter::new();
+        let _ = safety.set_limits(QTSHOCK_SAFETY.lock().unwrap().limits());
+        Self { app: app.clone(), safety: Arc::new(Mutex::new(safety)), cancel }
+    }
+
+    pub fn safety(&self) -> &Mutex<SafetyLimiter> {
+        &self.safety
+    }
+
+    // Patterns started by the dry run stop with it
+    pub fn is_over(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub fn report(&self, message: String) {
+        let message = format!("[dry run] {}", message);
+        println!("{}", message);
+        let _ = self.app.emit_all("vrc-osc-event", Payload { message });
+    }
+
+    // Stands in for the device request in trigger_qtshock
+    pub fn send(&self, shocker: &ShockerTarget, interaction: QTSInteraction, strength: u8, duration_ms: Option<u32>) {
+        let duration = match duration_ms {
+            Some(duration) => format!(" for {}ms", duration),
+            None => String::new()
+        };
+        self.report(format!("{:?} {}/{} at {}{}", interaction, shocker.device, shocker.shocker, strength, duration));
+    }
+}
+
+
+/*
+    One line of a recording. Packets are kept as raw bytes so malformed ones replay exactly
+    as they arrived, `at_ms` counts from the start of the recording.
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedPacket {
+    pub at_ms: u64,
+    pub from: SocketAddr,
+    pub data: String
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// Writes incoming packets as JSON lines while the VRChat integration records
+pub struct Recorder {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    started: Instant
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Couldn't create {}: {}", path.display(), e))?;
+        Ok(Self { writer: BufWriter::new(file), path: path.to_path_buf(), started: Instant::now() })
+    }
+
+    pub fn record(&mut self, from: SocketAddr, bytes: &[u8]) -> Result<(), String> {
+        let packet = RecordedPacket { at_ms: self.started.elapsed().as_millis() as u64, from, data: encode_hex(bytes) };
+        let line = serde_json::to_string(&packet).map_err(|e| e.to_string())?;
+        writeln!(self.writer, "{}", line).map_err(|e| format!("Couldn't write to {}: {}", self.path.display(), e))
+    }
+
+    pub fn finish(mut self) -> PathBuf {
+        if let Err(e) = self.writer.flush() {
+            println!("Failed to finish the OSC recording: {}", e);
+        }
+        self.path
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<RecordedPacket>, String> {
+    let file = File::open(path).map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?;
+    let mut packets = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let packet = serde_json::from_str::<RecordedPacket>(&line)
+            .map_err(|e| format!("Line {} of {} isn't a recorded packet: {}", i + 1, path.display(), e))?;
+        packets.push(packet);
+    }
+    Ok(packets)
+}
+
+// Replays going any slower or faster than this are more likely a typo than a test
+const MIN_REPLAY_SPEED: f32 = 0.1;
+const MAX_REPLAY_SPEED: f32 = 10.0;
+
+// 0 is allowed and replays without waiting
+pub fn check_speed(speed: f32) -> Result<(), String> {
+    if speed == 0.0 || (MIN_REPLAY_SPEED..=MAX_REPLAY_SPEED).contains(&speed) {
+        return Ok(());
+    }
+    Err(format!("The replay speed must be 0 or between {} and {}", MIN_REPLAY_SPEED, MAX_REPLAY_SPEED))
+}
+
+// How long to wait between two packets, a gap too long for a Duration waits for ever
+fn replay_wait(gap_ms: u64, speed: f32) -> Duration {
+    Duration::try_from_secs_f64(gap_ms as f64 / 1000.0 / speed as f64).unwrap_or(Duration::MAX)
+}
+
+// Frees the replay slot however the replay ends, a panic included
+struct ReplayGuard {
+    app: AppHandle,
+    cancel: CancellationToken
+}
+
+impl Drop for ReplayGuard {
+    fn drop(&mut self) {
+        // Also ends the dry run's patterns
+        self.cancel.cancel();
+        self.app.state::<VrcService>().finish_replay();
+    }
+}
+
+/*
+    Feeds a recording back through the VRChat packet handling, `speed` scales the recorded
+    timing and 0 replays as fast as possible. The replay keeps its own avatar, built-ins and
+    latches, so the live integration is left as it was. Anything still held or scheduled
+    when it ends is let go. With `dry_run` only the replay's own triggers go to the sink,
+    live ones carry on as usual.
+*/
+pub fn start_replay(app: &AppHandle, packets: Vec<RecordedPacket>, speed: f32, dry_run: bool, cancel: CancellationToken) {
+    let dry_run = if dry_run { Some(DryRun::new(app, cancel.clone())) } else { None };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let guard = ReplayGuard { app: app.clone(), cancel: cancel.clone() };
+        let ctx = OscContext::replay(&app.state::<VrcService>(), cancel.clone(), dry_run);
+        let count = packets.len();
+        let mut previous_ms = 0;
+        for packet in packets {
+            if speed > 0.0 {
+                let wait = replay_wait(packet.at_ms.saturating_sub(previous_ms), speed);
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            } else if cancel.is_cancelled() {
+                break;
+            }
+            previous_ms = packet.at_ms;
+            let decoded = decode_hex(&packet.data).ok_or("Bad hex".to_string())
+                .and_then(|bytes| rosc::decoder::decode_udp(&bytes).map(|(_, osc)| osc).map_err(|e| e.to_string()));
+            match decoded {
+                Ok(osc) => vrc::handle_packet(&app, &ctx, osc).await,
+                Err(e) => {
+                    let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Skipping malformed packet from {} at {}ms: {}", packet.from, packet.at_ms, e) });
+                }
+            }
+        }
+
+        let stopped = cancel.is_cancelled();
+        drop(guard);
+        let message = if stopped { "Replay stopped".to_string() } else { format!("Replayed {} packets", count) };
+        println!("{}", message);
+        let _ = app.emit_all("vrc-osc-event", Payload { message });
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_speeds_outside_the_range() {
+        for speed in [1e-30, 0.05, 10.5, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(check_speed(speed).is_err(), "{} was allowed", speed);
+        }
+        for speed in [0.0, MIN_REPLAY_SPEED, 1.0, MAX_REPLAY_SPEED] {
+            assert!(check_speed(speed).is_ok(), "{} was rejected", speed);
+        }
+    }
+
+    #[test]
+    fn long_gaps_dont_overflow() {
+        assert_eq!(replay_wait(1_000, 2.0), Duration::from_millis(500));
+        assert!(replay_wait(u64::MAX, MIN_REPLAY_SPEED) > Duration::from_secs(u32::MAX as u64));
+    }
+}
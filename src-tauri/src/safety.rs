@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::client::{MAX_STRENGTH, MIN_STRENGTH};
+use crate::defines::QTSInteraction;
+use crate::devices::ShockerTarget;
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+// Nothing the device does needs to run longer than this
+pub const MAX_DURATION_MS: u32 = 30_000;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OverLimitAction {
+    Reject,
+    Vibrate,
+    Beep
+}
+
+// Limits only apply to shocks, vibrate and beep are always let through.
+// A value of 0 disables the corresponding limit. The duration cap can't be disabled and applies to everything.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafetyLimits {
+    pub max_strength: u8,
+    pub max_duration_ms: u32,
+    // Keyed by "<device>" or "<device>/<shocker index>", the most specific entry wins
+    pub shocker_max_strength: BTreeMap<String, u8>,
+    pub min_shock_gap_ms: u64,
+    pub max_shocks_per_minute: u32,
+    pub max_shocks_per_hour: u32,
+    pub daily_shock_budget: u32,
+    pub over_limit_action: OverLimitAction
+}
+
+impl SafetyLimits {
+    pub const fn new() -> Self {
+        Self {
+            max_strength: MAX_STRENGTH,
+            max_duration_ms: 5_000,
+            shocker_max_strength: BTreeMap::new(),
+            min_shock_gap_ms: 500,
+            max_shocks_per_minute: 30,
+            max_shocks_per_hour: 0,
+            daily_shock_budget: 0,
+            over_limit_action: OverLimitAction::Vibrate
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&self.max_strength) {
+            return Err(format!("Max strength must be between {} and {}", MIN_STRENGTH, MAX_STRENGTH));
+        }
+        if self.max_duration_ms == 0 || self.max_duration_ms > MAX_DURATION_MS {
+            return Err(format!("Max duration must be between 1 and {}ms", MAX_DURATION_MS));
+        }
+        for (key, strength) in self.shocker_max_strength.iter() {
+            if !(MIN_STRENGTH..=MAX_STRENGTH).contains(strength) {
+                return Err(format!("Max strength for '{}' must be between {} and {}", key, MIN_STRENGTH, MAX_STRENGTH));
+            }
+        }
+        Ok(())
+    }
+
+    fn strength_cap(&self, shocker: &ShockerTarget) -> u8 {
+        let shocker_key = format!("{}/{}", shocker.device, shocker.shocker);
+        let cap = self.shocker_max_strength.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&shocker_key))
+            .or(self.shocker_max_strength.iter().find(|(key, _)| key.eq_ignore_ascii_case(&shocker.device)))
+            .map(|(_, cap)| *cap)
+            .unwrap_or(MAX_STRENGTH);
+        cap.min(self.max_strength)
+    }
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+// What actually gets sent after the limits have been applied, strength is the highest sent to any shocker
+#[derive(Clone, Debug)]
+pub struct SafetyVerdict {
+    pub interaction: QTSInteraction,
+    pub strength: u8,
+    pub reason: Option<String>,
+    // Why a shock was turned into something milder by the rate limits
+    pub rate_limit: Option<String>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SafetyUsage {
+    pub shocks_last_minute: usize,
+    pub shocks_last_hour: usize,
+    pub shocks_today: u32
+}
+
+pub struct SafetyLimiter {
+    limits: SafetyLimits,
+    recent_shocks: VecDeque<Instant>,
+    day: u64,
+    shocks_today: u32
+}
+
+impl SafetyLimiter {
+    pub const fn new() -> Self {
+        Self {
+            limits: SafetyLimits::new(),
+            recent_shocks: VecDeque::new(),
+            day: 0,
+            shocks_today: 0
+        }
+    }
+
+    fn today() -> u64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() / SECONDS_PER_DAY,
+            Err(_) => 0
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some(oldest) = self.recent_shocks.front() {
+            if now.duration_since(*oldest) < HOUR {
+                break;
+            }
+            self.recent_shocks.pop_front();
+        }
+        let today = Self::today();
+        if self.day != today {
+            self.day = today;
+            self.shocks_today = 0;
+        }
+    }
+
+    fn shocks_within(&self, now: Instant, window: Duration) -> usize {
+        self.recent_shocks.iter().filter(|t| now.duration_since(**t) < window).count()
+    }
+
+    // Returns why a shock can't be sent right now, if it can't
+    fn rate_limited(&self, now: Instant) -> Option<String> {
+        let limits = &self.limits;
+        if let Some(last) = self.recent_shocks.back() {
+            let gap = now.duration_since(*last);
+            if limits.min_shock_gap_ms > 0 && gap < Duration::from_millis(limits.min_shock_gap_ms) {
+                return Some(format!("Shocks must be at least {}ms apart", limits.min_shock_gap_ms));
+            }
+        }
+        if limits.max_shocks_per_minute > 0 && self.shocks_within(now, MINUTE) >= limits.max_shocks_per_minute as usize {
+            return Some(format!("Reached the limit of {} shocks per minute", limits.max_shocks_per_minute));
+        }
+        if limits.max_shocks_per_hour > 0 && self.shocks_within(now, HOUR) >= limits.max_shocks_per_hour as usize {
+            return Some(format!("Reached the limit of {} shocks per hour", limits.max_shocks_per_hour));
+        }
+        if limits.daily_shock_budget > 0 && self.shocks_today >= limits.daily_shock_budget {
+            return Some(format!("Used up the daily budget of {} shocks", limits.daily_shock_budget));
+        }
+        None
+    }
+
+    /*
+        Checks a single interaction request against the rate limits and records it.
+        Strength caps are per shocker, see cap_strength.
+    */
+    pub fn check(&mut self, interaction: QTSInteraction) -> Result<SafetyVerdict, String> {
+        if interaction != QTSInteraction::SHOCK {
+            return Ok(SafetyVerdict { interaction, strength: 0, reason: None, rate_limit: None });
+        }
+        let now = Instant::now();
+        self.prune(now);
+        match self.rate_limited(now) {
+            None => {
+                self.recent_shocks.push_back(now);
+                self.shocks_today += 1;
+                Ok(SafetyVerdict { interaction, strength: 0, reason: None, rate_limit: None })
+            },
+            Some(reason) => {
+                match self.limits.over_limit_action {
+                    OverLimitAction::Reject => Err(reason),
+                    OverLimitAction::Vibrate => Ok(SafetyVerdict { interaction: QTSInteraction::VIBRATE, strength: 0, reason: Some(format!("{}, vibrating instead", reason)), rate_limit: Some(reason) }),
+                    OverLimitAction::Beep => Ok(SafetyVerdict { interaction: QTSInteraction::BEEP, strength: 0, reason: Some(format!("{}, beeping instead", reason)), rate_limit: Some(reason) })
+                }
+            }
+        }
+    }
+
+    pub fn cap_strength(&self, interaction: QTSInteraction, strength: u8, shocker: &ShockerTarget) -> (u8, Option<String>) {
+        if interaction != QTSInteraction::SHOCK {
+            return (strength, None);
+        }
+        let cap = self.limits.strength_cap(shocker);
+        if strength > cap {
+            return (cap, Some(format!("Strength {} capped to {} for {}/{}", strength, cap, shocker.device, shocker.shocker)));
+        }
+        (strength, None)
+    }
+
+    // No duration leaves it to the device's own short pulse
+    pub fn cap_duration(&self, duration_ms: Option<u32>) -> (Option<u32>, Option<String>) {
+        let cap = self.limits.max_duration_ms;
+        match duration_ms {
+            Some(duration) if duration > cap => (Some(cap), Some(format!("Duration {}ms capped to {}ms", duration, cap))),
+            duration => (duration, None)
+        }
+    }
+
+    // How long until the oldest of the shocks filling a window drops out of it
+    fn window_wait(&self, now: Instant, window: Duration, max: u32) -> Option<Duration> {
+        let within: Vec<&Instant> = self.recent_shocks.iter().filter(|t| now.duration_since(**t) < window).collect();
+        if max == 0 || within.len() < max as usize {
+            return None;
+        }
+        let oldest = within[within.len() - max as usize];
+        Some(window.saturating_sub(now.duration_since(*oldest)))
+    }
+
+    // How long until a shock would get through the rate limits again, None if it would now
+    pub fn cooldown_remaining(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.prune(now);
+        self.rate_limited(now)?;
+        let limits = &self.limits;
+        let mut waits = vec![
+            self.window_wait(now, MINUTE, limits.max_shocks_per_minute),
+            self.window_wait(now, HOUR, limits.max_shocks_per_hour)
+        ];
+        if let Some(last) = self.recent_shocks.back() {
+            waits.push(Duration::from_millis(limits.min_shock_gap_ms).checked_sub(now.duration_since(*last)));
+        }
+        if limits.daily_shock_budget > 0 && self.shocks_today >= limits.daily_shock_budget {
+            let since_midnight = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() % SECONDS_PER_DAY).unwrap_or(0);
+            waits.push(Some(Duration::from_secs(SECONDS_PER_DAY - since_midnight)));
+        }
+        waits.into_iter().flatten().max()
+    }
+
+    // True while a shock right now would be held back by the rate limits
+    pub fn cooling_down(&mut self) -> bool {
+        self.cooldown_remaining().is_some()
+    }
+
+    pub fn limits(&self) -> SafetyLimits {
+        self.limits.clone()
+    }
+
+    pub fn set_limits(&mut self, limits: SafetyLimits) -> Result<(), String> {
+        limits.validate()?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    pub fn usage(&mut self) -> SafetyUsage {
+        let now = Instant::now();
+        self.prune(now);
+        SafetyUsage {
+            shocks_last_minute: self.shocks_within(now, MINUTE),
+            shocks_last_hour: self.shocks_within(now, HOUR),
+            shocks_today: self.shocks_today
+        }
+    }
+}
+
+pub static QTSHOCK_SAFETY: Mutex<SafetyLimiter> = Mutex::new(SafetyLimiter::new());
+
+
+#[tauri::command]
+pub fn get_safety_limits() -> SafetyLimits {
+    QTSHOCK_SAFETY.lock().unwrap().limits()
+}
+
+#[tauri::command]
+pub fn set_safety_limits(limits: SafetyLimits) -> Result<SafetyLimits, String> {
+    let mut safety = QTSHOCK_SAFETY.lock().unwrap();
+    safety.set_limits(limits)?;
+    crate::settings::changed();
+    Ok(safety.limits())
+}
+
+#[tauri::command]
+pub fn get_safety_usage() -> SafetyUsage {
+    QTSHOCK_SAFETY.lock().unwrap().usage()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_duration_ms: u32) -> SafetyLimiter {
+        let mut limiter = SafetyLimiter::new();
+        limiter.set_limits(SafetyLimits { max_duration_ms, ..SafetyLimits::default() }).unwrap();
+        limiter
+    }
+
+    #[test]
+    fn caps_a_long_duration() {
+        let (duration, reason) = limiter(2_000).cap_duration(Some(u32::MAX));
+        assert_eq!(duration, Some(2_000));
+        assert_eq!(reason, Some(format!("Duration {}ms capped to 2000ms", u32::MAX)));
+    }
+
+    #[test]
+    fn leaves_shorter_and_missing_durations_alone() {
+        let limiter = limiter(2_000);
+        assert_eq!(limiter.cap_duration(Some(2_000)), (Some(2_000), None));
+        assert_eq!(limiter.cap_duration(Some(300)), (Some(300), None));
+        assert_eq!(limiter.cap_duration(None), (None, None));
+    }
+
+    #[test]
+    fn rejects_an_unbounded_duration_cap() {
+        for max_duration_ms in [0, MAX_DURATION_MS + 1] {
+            let limits = SafetyLimits { max_duration_ms, ..SafetyLimits::default() };
+            assert!(limits.validate().is_err());
+        }
+        assert!(SafetyLimits { max_duration_ms: MAX_DURATION_MS, ..SafetyLimits::default() }.validate().is_ok());
+    }
+}
@@ -0,0 +1,563 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tauri::async_runtime::block_on;
+use tauri::{AppHandle, Manager, Wry};
+use tauri_plugin_store::{Store, StoreBuilder};
+
+use crate::avatars::AvatarProfile;
+use crate::chatbox::ChatboxConfig;
+use crate::client::{MAX_STRENGTH, MIN_STRENGTH};
+use crate::cs::CsService;
+use crate::defines::{QTSAction, QTSInteraction};
+use crate::devices::{Device, QTSHOCK_DEVICES};
+use crate::latch::Hysteresis;
+use crate::mappings::OscMapping;
+use crate::osc_filter::SourceFilter;
+use crate::patterns::{Pattern, QTSHOCK_PATTERNS};
+use crate::proportional::Proportional;
+use crate::safety::{SafetyLimits, QTSHOCK_SAFETY};
+use crate::vrc::VrcService;
+
+pub const SETTINGS_VERSION: u32 = 3;
+const SETTINGS_FILE: &str = "settings.json";
+const SETTINGS_KEY: &str = "settings";
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Strengths {
+    pub shock: u8,
+    pub vibrate: u8
+}
+
+impl Default for Strengths {
+    fn default() -> Self {
+        Self { shock: 10, vibrate: 80 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ports {
+    pub gsi: u16,
+    pub api: u16
+}
+
+impl Ports {
+    pub const fn new() -> Self {
+        Self { gsi: 3005, api: 3006 }
+    }
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*
+    Where the VRChat integration listens and sends. VRChat can run on another machine,
+    in which case `listen` has to be on a reachable interface and `send` points at it.
+    With OSCQuery the listen port is picked by the OS and `send` is only used until VRChat is found.
+    Packets are only accepted from `allowed_sources`, IPs or subnets like "192.168.1.0/24".
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscConfig {
+    pub listen: SocketAddr,
+    pub send: SocketAddr,
+    // 0 lets the OS pick a free port
+    pub local_send_port: u16,
+    pub oscquery: bool,
+    // Publish device and safety state back to the avatar, see feedback.rs
+    pub feedback: bool,
+    pub allowed_sources: Vec<String>,
+    // Per sender, 0 turns the limit off
+    pub max_packets_per_second: u32
+}
+
+impl OscConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.listen.port() == 0 && !self.oscquery {
+            return Err("The OSC listen port can't be 0".to_string());
+        }
+        if self.send.port() == 0 || self.send.ip().is_unspecified() {
+            return Err(format!("{} isn't an address OSC can be sent to", self.send));
+        }
+        SourceFilter::new(&self.allowed_sources, self.max_packets_per_second)?;
+        Ok(())
+    }
+
+    // Sending from a loopback socket can only reach this machine, the socket has to match the send address's family
+    pub fn local_send_addr(&self) -> SocketAddr {
+        let ip = match (self.send.is_ipv6(), self.send.ip().is_loopback()) {
+            (false, true) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            (false, false) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            (true, true) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            (true, false) => IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        };
+        SocketAddr::new(ip, self.local_send_port)
+    }
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9001),
+            send: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9000),
+            local_send_port: 7766,
+            oscquery: false,
+            feedback: true,
+            allowed_sources: vec!["127.0.0.0/8".to_string(), "::1".to_string()],
+            // VRChat sends every changed parameter, a busy avatar easily reaches a few hundred
+            max_packets_per_second: 1000
+        }
+    }
+}
+
+// How PUSH and PROP parameters latch and how PROP maps onto strength, overridable per parameter name
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscTriggers {
+    pub push: Hysteresis,
+    pub push_overrides: BTreeMap<String, Hysteresis>,
+    pub proportional: Proportional,
+    pub proportional_overrides: BTreeMap<String, Proportional>
+}
+
+impl OscTriggers {
+    pub fn validate(&self) -> Result<(), String> {
+        self.push.validate()?;
+        for (parameter, hysteresis) in self.push_overrides.iter() {
+            hysteresis.validate().map_err(|e| format!("{}: {}", parameter, e))?;
+        }
+        self.proportional.validate()?;
+        for (parameter, proportional) in self.proportional_overrides.iter() {
+            proportional.validate().map_err(|e| format!("{}: {}", parameter, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Integrations {
+    pub vrc_enabled: bool,
+    pub cs_enabled: bool
+}
+
+impl Integrations {
+    pub const fn new() -> Self {
+        Self { vrc_enabled: false, cs_enabled: false }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    pub cs_target: String,
+    pub cs_death_action: QTSAction
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self { cs_target: String::new(), cs_death_action: QTSAction::Interaction(QTSInteraction::SHOCK) }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub devices: Vec<Device>,
+    pub strengths: Strengths,
+    pub safety: SafetyLimits,
+    pub ports: Ports,
+    pub osc: OscConfig,
+    pub osc_triggers: OscTriggers,
+    pub osc_mappings: Vec<OscMapping>,
+    pub osc_chatbox: ChatboxConfig,
+    pub avatar_profiles: Vec<AvatarProfile>,
+    pub integrations: Integrations,
+    pub patterns: Vec<Pattern>,
+    pub rules: Rules
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            devices: Vec::new(),
+            strengths: Strengths::default(),
+            safety: SafetyLimits::default(),
+            ports: Ports::default(),
+            osc: OscConfig::default(),
+            osc_triggers: OscTriggers::default(),
+            osc_mappings: Vec::new(),
+            osc_chatbox: ChatboxConfig::default(),
+            avatar_profiles: Vec::new(),
+            integrations: Integrations::default(),
+            patterns: Vec::new(),
+            rules: Rules::default()
+        }
+    }
+}
+
+fn check_strength(name: &str, strength: u8) -> Result<(), String> {
+    if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+        return Err(format!("{} strength must be between {} and {}", name, MIN_STRENGTH, MAX_STRENGTH));
+    }
+    Ok(())
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        check_strength("Shock", self.strengths.shock)?;
+        check_strength("Vibrate", self.strengths.vibrate)?;
+        self.safety.validate()?;
+        for (i, device) in self.devices.iter().enumerate() {
+            if self.devices.iter().skip(i + 1).any(|d| d.name.eq_ignore_ascii_case(&device.name)) {
+                return Err(format!("Device '{}' is listed twice", device.name));
+            }
+        }
+        for pattern in self.patterns.iter() {
+            pattern.validate()?;
+        }
+        let ports = &self.ports;
+        if [ports.gsi, ports.api].contains(&0) {
+            return Err("Ports can't be 0".to_string());
+        }
+        self.osc.validate()?;
+        self.osc_triggers.validate()?;
+        for (i, mapping) in self.osc_mappings.iter().enumerate() {
+            mapping.validate()?;
+            if self.osc_mappings.iter().skip(i + 1).any(|m| m.name.eq_ignore_ascii_case(&mapping.name)) {
+                return Err(format!("Mapping '{}' is listed twice", mapping.name));
+            }
+        }
+        self.osc_chatbox.validate()?;
+        for (i, profile) in self.avatar_profiles.iter().enumerate() {
+            profile.validate()?;
+            if self.avatar_profiles.iter().skip(i + 1).any(|p| p.id == profile.id) {
+                return Err(format!("Avatar {} has two profiles", profile.id));
+            }
+        }
+        Ok(())
+    }
+
+    // Repairs anything invalid in loaded settings instead of throwing the whole file away
+    fn sanitize(mut self) -> Self {
+        let defaults = Settings::default();
+        if check_strength("Shock", self.strengths.shock).is_err() || check_strength("Vibrate", self.strengths.vibrate).is_err() {
+            println!("Invalid strengths in settings, resetting to defaults");
+            self.strengths = defaults.strengths;
+        }
+        if let Err(e) = self.safety.validate() {
+            println!("Invalid safety limits in settings, resetting to defaults: {}", e);
+            self.safety = defaults.safety;
+        }
+        self.patterns.retain(|pattern| match pattern.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Dropping invalid pattern from settings: {}", e);
+                false
+            }
+        });
+        let mut devices: Vec<Device> = Vec::new();
+        for device in self.devices.drain(..) {
+            if !devices.iter().any(|d| d.name.eq_ignore_ascii_case(&device.name)) {
+                devices.push(device);
+            }
+        }
+        self.devices = devices;
+        if let Err(e) = self.osc.validate() {
+            println!("Invalid OSC addresses in settings, resetting to defaults: {}", e);
+            self.osc = defaults.osc;
+        }
+        if let Err(e) = self.osc_triggers.validate() {
+            println!("Invalid OSC trigger thresholds in settings, resetting to defaults: {}", e);
+            self.osc_triggers = defaults.osc_triggers;
+        }
+        let mut mappings: Vec<OscMapping> = Vec::new();
+        for mapping in self.osc_mappings.drain(..) {
+            if let Err(e) = mapping.validate() {
+                println!("Dropping invalid OSC mapping from settings: {}", e);
+            } else if !mappings.iter().any(|m| m.name.eq_ignore_ascii_case(&mapping.name)) {
+                mappings.push(mapping);
+            }
+        }
+        self.osc_mappings = mappings;
+        if let Err(e) = self.osc_chatbox.validate() {
+            println!("Invalid chatbox settings, resetting to defaults: {}", e);
+            self.osc_chatbox = defaults.osc_chatbox;
+        }
+        let mut profiles: Vec<AvatarProfile> = Vec::new();
+        for profile in self.avatar_profiles.drain(..) {
+            if let Err(e) = profile.validate() {
+                println!("Dropping invalid avatar profile from settings: {}", e);
+            } else if !profiles.iter().any(|p| p.id == profile.id) {
+                profiles.push(profile);
+            }
+        }
+        self.avatar_profiles = profiles;
+        self
+    }
+
+    // Snapshot of everything currently configured at runtime
+    pub fn collect() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            devices: QTSHOCK_DEVICES.lock().unwrap().clone(),
+            strengths: Strengths {
+                shock: *crate::QTSHOCK_SHK_STRENGTH.lock().unwrap(),
+                vibrate: *crate::QTSHOCK_VIB_STRENGTH.lock().unwrap()
+            },
+            safety: QTSHOCK_SAFETY.lock().unwrap().limits(),
+            ports: QTSHOCK_PORTS.lock().unwrap().clone(),
+            osc: match SETTINGS_APP.get() {
+                Some(app) => app.state::<VrcService>().osc_config(),
+                None => OscConfig::default()
+            },
+            osc_triggers: match SETTINGS_APP.get() {
+                Some(app) => app.state::<VrcService>().triggers(),
+                None => OscTriggers::default()
+            },
+            osc_mappings: match SETTINGS_APP.get() {
+                Some(app) => app.state::<VrcService>().mappings(),
+                None => Vec::new()
+            },
+            osc_chatbox: match SETTINGS_APP.get() {
+                Some(app) => app.state::<VrcService>().chatbox_config(),
+                None => ChatboxConfig::default()
+            },
+            avatar_profiles: match SETTINGS_APP.get() {
+                Some(app) => app.state::<VrcService>().avatar_profiles(),
+                None => Vec::new()
+            },
+            integrations: QTSHOCK_INTEGRATIONS.lock().unwrap().clone(),
+            patterns: QTSHOCK_PATTERNS.lock().unwrap().clone(),
+            rules: match SETTINGS_APP.get() {
+                Some(app) => app.state::<CsService>().rules(),
+                None => Rules::default()
+            }
+        }
+    }
+
+    fn apply(self) {
+        *QTSHOCK_DEVICES.lock().unwrap() = self.devices;
+        *crate::QTSHOCK_SHK_STRENGTH.lock().unwrap() = self.strengths.shock;
+        *crate::QTSHOCK_VIB_STRENGTH.lock().unwrap() = self.strengths.vibrate;
+        if let Err(e) = QTSHOCK_SAFETY.lock().unwrap().set_limits(self.safety) {
+            println!("Failed to apply safety limits: {}", e);
+        }
+        *QTSHOCK_PORTS.lock().unwrap() = self.ports;
+        if let Some(app) = SETTINGS_APP.get() {
+            app.state::<VrcService>().set_osc_config(self.osc);
+            app.state::<VrcService>().set_triggers(self.osc_triggers);
+            app.state::<VrcService>().set_mappings(self.osc_mappings);
+            app.state::<VrcService>().set_chatbox_config(self.osc_chatbox);
+            app.state::<VrcService>().set_avatar_profiles(self.avatar_profiles);
+        }
+        *QTSHOCK_INTEGRATIONS.lock().unwrap() = self.integrations;
+        *QTSHOCK_PATTERNS.lock().unwrap() = self.patterns;
+        if let Some(app) = SETTINGS_APP.get() {
+            app.state::<CsService>().set_rules(self.rules);
+        }
+    }
+}
+
+
+pub static QTSHOCK_PORTS: Mutex<Ports> = Mutex::new(Ports::new());
+pub static QTSHOCK_INTEGRATIONS: Mutex<Integrations> = Mutex::new(Integrations::new());
+
+static SETTINGS_STORE: Mutex<Option<Store<Wry>>> = Mutex::new(None);
+static SETTINGS_APP: OnceLock<AppHandle> = OnceLock::new();
+
+
+/*
+    Upgrades settings saved by older versions one version at a time.
+    Unversioned settings are treated as version 0.
+*/
+fn migrate(mut value: JsonValue) -> JsonValue {
+    let mut version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    while version < SETTINGS_VERSION {
+        match version {
+            // Nothing was persisted before version 1
+            0 => {},
+            // OSC moved from three loopback ports to full addresses
+            1 => migrate_osc_ports(&mut value),
+            // Incoming OSC is filtered by sender
+            2 => migrate_osc_sources(&mut value),
+            _ => {}
+        }
+        version += 1;
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), JsonValue::from(SETTINGS_VERSION));
+    }
+    value
+}
+
+fn migrate_osc_ports(value: &mut JsonValue) {
+    let ports = match value.get_mut("ports").and_then(|p| p.as_object_mut()) {
+        Some(ports) => ports,
+        None => {
+            return;
+        }
+    };
+    let defaults = OscConfig::default();
+    let mut port = |key: &str, default: u16| -> u16 {
+        match ports.remove(key).and_then(|p| p.as_u64()) {
+            Some(port) => port as u16,
+            None => default
+        }
+    };
+    let osc = OscConfig {
+        listen: SocketAddr::new(defaults.listen.ip(), port("osc_receive", defaults.listen.port())),
+        send: SocketAddr::new(defaults.send.ip(), port("osc_send", defaults.send.port())),
+        local_send_port: port("osc_local_send", defaults.local_send_port),
+        ..defaults
+    };
+    if let (Some(object), Ok(osc)) = (value.as_object_mut(), serde_json::to_value(osc)) {
+        object.insert("osc".to_string(), osc);
+    }
+}
+
+// VRChat on another machine sends from the address we send to, keep accepting it
+fn migrate_osc_sources(value: &mut JsonValue) {
+    let osc = match value.get_mut("osc").and_then(|o| o.as_object_mut()) {
+        Some(osc) => osc,
+        None => {
+            return;
+        }
+    };
+    let send = osc.get("send").and_then(|s| s.as_str()).and_then(|s| s.parse::<SocketAddr>().ok());
+    let mut allowed_sources = OscConfig::default().allowed_sources;
+    if let Some(send) = send {
+        if !send.ip().is_loopback() {
+            allowed_sources.push(send.ip().to_string());
+        }
+    }
+    osc.insert("allowed_sources".to_string(), JsonValue::from(allowed_sources));
+}
+
+pub fn load(app: &AppHandle) -> Settings {
+    let _ = SETTINGS_APP.set(app.clone());
+    let mut store = StoreBuilder::new(app.clone(), PathBuf::from(SETTINGS_FILE)).build();
+    if let Err(e) = store.load() {
+        // Missing on first launch, anything else means the file is broken
+        println!("Couldn't load settings, using defaults: {}", e);
+    }
+
+    let settings = match store.get(SETTINGS_KEY) {
+        Some(value) => {
+            match serde_json::from_value::<Settings>(migrate(value.clone())) {
+                Ok(settings) => settings.sanitize(),
+                Err(e) => {
+                    println!("Couldn't read settings, using defaults: {}", e);
+                    Settings::default()
+                }
+            }
+        },
+        None => Settings::default()
+    };
+    *SETTINGS_STORE.lock().unwrap() = Some(store);
+    settings.clone().apply();
+    settings
+}
+
+pub fn save() {
+    // Collected under the store lock so overlapping saves can't write or announce an older snapshot last
+    let mut store_lock = SETTINGS_STORE.lock().unwrap();
+    let store = match store_lock.as_mut() {
+        Some(store) => store,
+        None => {
+            return;
+        }
+    };
+    let settings = Settings::collect();
+    let value = match serde_json::to_value(&settings) {
+        Ok(value) => value,
+        Err(e) => {
+            println!("Failed to serialize settings: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = store.insert(SETTINGS_KEY.to_string(), value) {
+        println!("Failed to update settings: {}", e);
+        return;
+    }
+    if let Err(e) = store.save() {
+        println!("Failed to save settings: {}", e);
+        return;
+    }
+    if let Some(app) = SETTINGS_APP.get() {
+        let _ = app.emit_all("settings-changed", settings);
+    }
+}
+
+// Saves in the background so callers can notify while still holding their own locks
+pub fn changed() {
+    tauri::async_runtime::spawn_blocking(save);
+}
+
+pub fn set_integration(vrc_enabled: Option<bool>, cs_enabled: Option<bool>) {
+    {
+        let mut integrations = QTSHOCK_INTEGRATIONS.lock().unwrap();
+        if let Some(enabled) = vrc_enabled {
+            integrations.vrc_enabled = enabled;
+        }
+        if let Some(enabled) = cs_enabled {
+            integrations.cs_enabled = enabled;
+        }
+    }
+    changed();
+}
+
+pub fn ports() -> Ports {
+    QTSHOCK_PORTS.lock().unwrap().clone()
+}
+
+
+#[tauri::command]
+pub fn get_settings() -> Settings {
+    Settings::collect()
+}
+
+/*
+    Replaces everything at once. The integrations are then turned on, off or restarted the way
+    their own commands would, so what's running matches what was saved. The local API only
+    moves to a new port on the next launch.
+*/
+#[tauri::command]
+pub fn set_settings(app: AppHandle, settings: Settings) -> Result<Settings, String> {
+    settings.validate()?;
+    let before = Settings::collect();
+    let wanted = settings.integrations.clone();
+    // Left as they were until the toggles below have actually changed them
+    let settings = Settings { integrations: before.integrations.clone(), ..settings };
+    let (osc_changed, gsi_changed) = (settings.osc != before.osc, settings.ports.gsi != before.ports.gsi);
+    settings.apply();
+    changed();
+
+    let vrc = if wanted.vrc_enabled != before.integrations.vrc_enabled {
+        crate::vrc::set_enabled(&app, wanted.vrc_enabled)
+    } else if osc_changed {
+        crate::vrc::restart(&app)
+    } else {
+        Ok(())
+    };
+    let cs = if wanted.cs_enabled != before.integrations.cs_enabled {
+        block_on(crate::cs::set_enabled(&app, wanted.cs_enabled))
+    } else if wanted.cs_enabled && gsi_changed {
+        block_on(crate::cs::restart(&app))
+    } else {
+        Ok(())
+    };
+    vrc.and(cs)?;
+    Ok(Settings::collect())
+}
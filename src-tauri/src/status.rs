@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+use crate::cs::CsService;
+use crate::vrc::VrcService;
+
+// Packets arrive many times a second, the UI only needs to hear about them this often
+const PACKET_EMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrationState {
+    Stopped,
+    Running,
+    Error
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IntegrationStatus {
+    pub integration: String,
+    pub state: IntegrationState,
+    pub address: Option<String>,
+    // Unix time in milliseconds
+    pub last_packet_ms: Option<u64>,
+    pub packets_received: u64,
+    pub packets_handled: u64,
+    // Rejected before being looked at, see osc_filter.rs
+    pub packets_dropped: u64,
+    // Couldn't be decoded or were missing their value
+    pub packets_malformed: u64,
+    pub last_error: Option<String>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IntegrationStatuses {
+    pub vrc: IntegrationStatus,
+    pub cs: IntegrationStatus
+}
+
+/*
+    Keeps the status of one integration and emits "integration-status" whenever it changes.
+    Packet updates are throttled, everything else is emitted straight away.
+*/
+pub struct StatusTracker {
+    status: Mutex<IntegrationStatus>,
+    last_emit: Mutex<Option<Instant>>
+}
+
+fn now_ms() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_millis() as u64,
+        Err(_) => 0
+    }
+}
+
+impl StatusTracker {
+    pub fn new(integration: &str) -> Self {
+        Self {
+            status: Mutex::new(IntegrationStatus {
+                integration: integration.to_string(),
+                state: IntegrationState::Stopped,
+                address: None,
+                last_packet_ms: None,
+                packets_received: 0,
+                packets_handled: 0,
+                packets_dropped: 0,
+                packets_malformed: 0,
+                last_error: None
+            }),
+            last_emit: Mutex::new(None)
+        }
+    }
+
+    pub fn get(&self) -> IntegrationStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn emit(&self, app: &AppHandle) {
+        let status = self.get();
+        *self.last_emit.lock().unwrap() = Some(Instant::now());
+        let _ = app.emit_all("integration-status", status);
+    }
+
+    fn update<F: FnOnce(&mut IntegrationStatus)>(&self, app: &AppHandle, f: F) {
+        f(&mut self.status.lock().unwrap());
+        self.emit(app);
+    }
+
+    pub fn started(&self, app: &AppHandle, address: String) {
+        self.update(app, |status| {
+            status.state = IntegrationState::Running;
+            status.address = Some(address);
+            status.last_packet_ms = None;
+            status.packets_received = 0;
+            status.packets_handled = 0;
+            status.packets_dropped = 0;
+            status.packets_malformed = 0;
+            status.last_error = None;
+        });
+    }
+
+    pub fn stopped(&self, app: &AppHandle) {
+        self.update(app, |status| {
+            status.state = IntegrationState::Stopped;
+            status.address = None;
+        });
+    }
+
+    // The integration couldn't start or died on its own
+    pub fn failed(&self, app: &AppHandle, error: String) {
+        self.update(app, |status| {
+            status.state = IntegrationState::Error;
+            status.address = None;
+            status.last_error = Some(error);
+        });
+    }
+
+    // Something went wrong while the integration kept running
+    pub fn error(&self, app: &AppHandle, error: String) {
+        self.update(app, |status| {
+            status.last_error = Some(error);
+        });
+    }
+
+    pub fn packet(&self, app: &AppHandle) {
+        {
+            let mut status = self.status.lock().unwrap();
+            status.last_packet_ms = Some(now_ms());
+            status.packets_received += 1;
+        }
+        self.emit_throttled(app);
+    }
+
+    pub fn dropped(&self, app: &AppHandle) {
+        self.status.lock().unwrap().packets_dropped += 1;
+        self.emit_throttled(app);
+    }
+
+    // Reported like packets, a sender spamming bad data shouldn't flood the UI
+    pub fn malformed(&self, app: &AppHandle, error: String) {
+        {
+            let mut status = self.status.lock().unwrap();
+            status.packets_malformed += 1;
+            status.last_error = Some(error);
+        }
+        self.emit_throttled(app);
+    }
+
+    fn emit_throttled(&self, app: &AppHandle) {
+        let due = match *self.last_emit.lock().unwrap() {
+            Some(last) => last.elapsed() >= PACKET_EMIT_INTERVAL,
+            None => true
+        };
+        if due {
+            self.emit(app);
+        }
+    }
+
+    // Counts a packet that QTHub acted on, reported with the next packet update
+    pub fn handled(&self) {
+        self.status.lock().unwrap().packets_handled += 1;
+    }
+}
+
+
+#[tauri::command]
+pub fn get_integration_status(vrc: State<'_, VrcService>, cs: State<'_, CsService>) -> IntegrationStatuses {
+    IntegrationStatuses { vrc: vrc.status.get(), cs: cs.status.get() }
+}
+
+pub fn all(app: &AppHandle) -> IntegrationStatuses {
+    get_integration_status(app.state::<VrcService>(), app.state::<CsService>())
+}
@@ -0,0 +1,1412 @@
+use rfd::FileDialog;
+use rosc::{encoder, OscMessage, OscPacket, OscTime, OscType};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::async_runtime::block_on;
+use tauri::{AppHandle, Manager, State};
+use tokio_util::sync::CancellationToken;
+
+use crate::avatars::{self, AvatarGate, AvatarProfile};
+use crate::chatbox::{ChatboxConfig, ChatboxLimiter};
+use crate::client::{DeviceError, MAX_STRENGTH, MIN_STRENGTH};
+use crate::defines::{QTSAction, QTSInteraction, QTSOSCType};
+use crate::feedback::{self, Feedback};
+use crate::latch::{Hysteresis, LatchEvent, Latches};
+use crate::mappings::{Builtins, EdgeResult, MappingCondition, MappingEdges, OscMapping, VrcBuiltin};
+use crate::osc_address::{parse_qts_address, QtsAddress, QtsAddressError};
+use crate::osc_filter::SourceFilter;
+use crate::oscquery::OscQuery;
+use crate::proportional::Proportional;
+use crate::recording::{self, DryRun, Recorder};
+use crate::safety::{SafetyVerdict, QTSHOCK_SAFETY};
+use crate::settings::{self, OscConfig, OscTriggers};
+use crate::status::StatusTracker;
+use crate::{devices, emergency, patterns, Payload, TriggerScope};
+
+// How often the receiver wakes up to check whether it has been stopped
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(250);
+// OSC timetags count from 1900, the Unix epoch is 70 years later
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+// Anything scheduled further out than this is almost certainly a clock mismatch
+const MAX_BUNDLE_DELAY: Duration = Duration::from_secs(60);
+// The largest UDP payload there can be, rosc's MTU sized buffer cuts bigger packets short
+const MAX_DATAGRAM: usize = 65_536;
+// Windows' error for a datagram bigger than the buffer
+const WSAEMSGSIZE: i32 = 10040;
+const AVATAR_CHANGE_ADDRESS: &str = "/avatar/change";
+const CHATBOX_ADDRESS: &str = "/chatbox/input";
+// The avatar's strength radials, written by both QTHub and the in-game menu
+const SHOCK_STRENGTH_PARAMETER: &str = "QTS_IN_SHOCK_STRENGTH";
+const VIBRATE_STRENGTH_PARAMETER: &str = "QTS_IN_VIBRATE_STRENGTH";
+
+
+struct VrcRuntime {
+    cancel: Option<CancellationToken>,
+    receiver: Option<thread::JoinHandle<()>>,
+    oscquery: Option<OscQuery>
+}
+
+// What the packet handling remembers from one message to the next
+struct OscState {
+    latches: Latches,
+    edges: MappingEdges,
+    builtins: Builtins,
+    // Last reported by /avatar/change
+    avatar: Mutex<Option<String>>
+}
+
+impl OscState {
+    fn new(avatar: Option<String>) -> Self {
+        Self { latches: Latches::new(), edges: MappingEdges::new(), builtins: Builtins::new(), avatar: Mutex::new(avatar) }
+    }
+
+    // Lets go of anything held or half-way through an edge
+    fn clear(&self) {
+        self.latches.clear();
+        self.edges.clear();
+    }
+}
+
+/*
+    Who a packet is handled for. Live packets share the integration's state and stop with it,
+    a replay brings its own state and token so it can't change what the live avatar is doing.
+    A dry-run replay's triggers go to its sink, see recording.rs.
+*/
+#[derive(Clone)]
+pub struct OscContext {
+    state: Arc<OscState>,
+    cancel: CancellationToken,
+    replay: bool,
+    dry_run: Option<DryRun>
+}
+
+impl OscContext {
+    // Starts from the live avatar, so recordings made without an avatar change still get through the gate
+    pub fn replay(vrc: &VrcService, cancel: CancellationToken, dry_run: Option<DryRun>) -> Self {
+        Self { state: Arc::new(OscState::new(vrc.avatar())), cancel, replay: true, dry_run }
+    }
+
+    fn scope(&self, profile: Option<&AvatarProfile>) -> TriggerScope {
+        TriggerScope { caps: profile.map(|profile| profile.caps()).unwrap_or_default(), dry_run: self.dry_run.clone() }
+    }
+
+    // Scheduled messages and held triggers are dropped once this is false
+    fn is_running(&self) -> bool {
+        !self.cancel.is_cancelled()
+    }
+}
+
+/*
+    The VRChat OSC integration. Held in Tauri's managed state, the receiver runs on its
+    own thread until its cancellation token fires and the sender socket lives as long as it.
+*/
+pub struct VrcService {
+    runtime: Mutex<VrcRuntime>,
+    sender: Mutex<Option<UdpSocket>>,
+    config: Mutex<OscConfig>,
+    triggers: Mutex<OscTriggers>,
+    mappings: Mutex<Vec<OscMapping>>,
+    chatbox: Mutex<ChatboxConfig>,
+    chatbox_limiter: ChatboxLimiter,
+    avatar_profiles: Mutex<Vec<AvatarProfile>>,
+    // Kept across restarts, VRChat only sends the avatar when it changes
+    live: Arc<OscState>,
+    recorder: Mutex<Option<Recorder>>,
+    replay: Mutex<Option<CancellationToken>>,
+    // Found through OSCQuery, takes priority over the configured send address
+    vrchat_addr: Mutex<Option<SocketAddr>>,
+    pub feedback: Feedback,
+    pub status: StatusTracker
+}
+
+impl VrcService {
+    pub fn new() -> Self {
+        Self {
+            runtime: Mutex::new(VrcRuntime { cancel: None, receiver: None, oscquery: None }),
+            sender: Mutex::new(None),
+            config: Mutex::new(OscConfig::default()),
+            triggers: Mutex::new(OscTriggers::default()),
+            mappings: Mutex::new(Vec::new()),
+            chatbox: Mutex::new(ChatboxConfig::default()),
+            chatbox_limiter: ChatboxLimiter::new(),
+            avatar_profiles: Mutex::new(Vec::new()),
+            live: Arc::new(OscState::new(None)),
+            recorder: Mutex::new(None),
+            replay: Mutex::new(None),
+            vrchat_addr: Mutex::new(None),
+            feedback: Feedback::new(),
+            status: StatusTracker::new("vrc")
+        }
+    }
+
+    pub fn osc_config(&self) -> OscConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    // Takes effect the next time the integration starts
+    pub fn set_osc_config(&self, config: OscConfig) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    pub fn triggers(&self) -> OscTriggers {
+        self.triggers.lock().unwrap().clone()
+    }
+
+    pub fn set_triggers(&self, triggers: OscTriggers) {
+        *self.triggers.lock().unwrap() = triggers;
+    }
+
+    pub fn mappings(&self) -> Vec<OscMapping> {
+        self.mappings.lock().unwrap().clone()
+    }
+
+    pub fn set_mappings(&self, mappings: Vec<OscMapping>) {
+        *self.mappings.lock().unwrap() = mappings;
+        self.live.edges.clear();
+    }
+
+    pub fn chatbox_config(&self) -> ChatboxConfig {
+        self.chatbox.lock().unwrap().clone()
+    }
+
+    pub fn set_chatbox_config(&self, config: ChatboxConfig) {
+        *self.chatbox.lock().unwrap() = config;
+    }
+
+    pub fn avatar_profiles(&self) -> Vec<AvatarProfile> {
+        self.avatar_profiles.lock().unwrap().clone()
+    }
+
+    pub fn set_avatar_profiles(&self, profiles: Vec<AvatarProfile>) {
+        *self.avatar_profiles.lock().unwrap() = profiles;
+    }
+
+    // Does nothing unless recording, stops recording if the file can't be written
+    fn record(&self, from: SocketAddr, bytes: &[u8]) -> Result<(), String> {
+        let mut recorder = self.recorder.lock().unwrap();
+        let res = match recorder.as_mut() {
+            Some(recorder) => recorder.record(from, bytes),
+            None => {
+                return Ok(());
+            }
+        };
+        if res.is_err() {
+            if let Some(recorder) = recorder.take() {
+                recorder.finish();
+            }
+        }
+        res
+    }
+
+    pub fn finish_replay(&self) {
+        *self.replay.lock().unwrap() = None;
+    }
+
+    pub fn avatar(&self) -> Option<String> {
+        self.live.avatar.lock().unwrap().clone()
+    }
+
+    fn avatar_gate(&self, state: &OscState) -> AvatarGate {
+        avatars::gate(&self.avatar_profiles.lock().unwrap(), state.avatar.lock().unwrap().as_deref())
+    }
+
+    // Overrides are keyed by parameter name, the part after /avatar/parameters/
+    fn hysteresis_for(&self, address: &str) -> Hysteresis {
+        let triggers = self.triggers.lock().unwrap();
+        let parameter = address.rsplit('/').next().unwrap_or(address);
+        match triggers.push_overrides.get(parameter) {
+            Some(hysteresis) => hysteresis.clone(),
+            None => triggers.push.clone()
+        }
+    }
+
+    // A strength in the address caps the configured maximum
+    fn proportional_for(&self, address: &str, max_strength: Option<u8>) -> Proportional {
+        let triggers = self.triggers.lock().unwrap();
+        let parameter = address.rsplit('/').next().unwrap_or(address);
+        let mut proportional = match triggers.proportional_overrides.get(parameter) {
+            Some(proportional) => proportional.clone(),
+            None => triggers.proportional.clone()
+        };
+        if let Some(max_strength) = max_strength {
+            proportional.max_strength = proportional.max_strength.min(max_strength);
+            proportional.min_strength = proportional.min_strength.min(max_strength);
+        }
+        proportional
+    }
+
+    pub fn set_vrchat_addr(&self, addr: Option<SocketAddr>) {
+        *self.vrchat_addr.lock().unwrap() = addr;
+        if addr.is_some() {
+            self.feedback.resync();
+            self.send_strength(QTSInteraction::SHOCK, *crate::QTSHOCK_SHK_STRENGTH.lock().unwrap());
+            self.send_strength(QTSInteraction::VIBRATE, *crate::QTSHOCK_VIB_STRENGTH.lock().unwrap());
+        }
+    }
+
+    fn send_addr(&self) -> SocketAddr {
+        match *self.vrchat_addr.lock().unwrap() {
+            Some(addr) => addr,
+            None => self.config.lock().unwrap().send
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        match self.runtime.lock().unwrap().cancel.as_ref() {
+            Some(cancel) => !cancel.is_cancelled(),
+            None => false
+        }
+    }
+
+    // Binds both sockets before anything is spawned so a port in use is reported instead of panicking a thread
+    pub fn start(&self, app: &AppHandle) -> Result<(), String> {
+        let mut runtime = self.runtime.lock().unwrap();
+        if let Some(cancel) = runtime.cancel.as_ref() {
+            if !cancel.is_cancelled() {
+                return Ok(());
+            }
+        }
+        // A receiver that stopped on its own still has to be reaped
+        if let Some(receiver) = runtime.receiver.take() {
+            let _ = receiver.join();
+        }
+
+        let config = self.osc_config();
+        // OSCQuery tells VRChat where we are, so any free port will do
+        let bind_addr = if config.oscquery { SocketAddr::new(config.listen.ip(), 0) } else { config.listen };
+        let receive_sock = UdpSocket::bind(bind_addr).map_err(|e| format!("Couldn't listen on {}: {}", bind_addr, e))?;
+        let receive_addr = receive_sock.local_addr().map_err(|e| format!("Couldn't read the OSC socket address: {}", e))?;
+        receive_sock.set_read_timeout(Some(RECEIVE_TIMEOUT)).map_err(|e| format!("Couldn't configure the OSC socket: {}", e))?;
+        let filter = SourceFilter::new(&config.allowed_sources, config.max_packets_per_second)?;
+        let send_addr = config.local_send_addr();
+        let send_sock = UdpSocket::bind(send_addr).map_err(|e| format!("Couldn't bind {}: {}", send_addr, e))?;
+
+        let cancel = CancellationToken::new();
+        *self.vrchat_addr.lock().unwrap() = None;
+        let oscquery = if config.oscquery {
+            Some(OscQuery::start(app, receive_addr, cancel.clone())?)
+        } else {
+            None
+        };
+        let receiver_app = app.clone();
+        let receiver_ctx = OscContext { state: self.live.clone(), cancel: cancel.clone(), replay: false, dry_run: None };
+        let receiver = thread::Builder::new()
+            .name("vrc-osc".to_string())
+            .spawn(move || {
+                receive_loop(receiver_app, receive_sock, filter, receiver_ctx);
+            })
+            .map_err(|e| format!("Couldn't start the OSC receiver: {}", e));
+        let receiver = match receiver {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                if let Some(oscquery) = oscquery {
+                    oscquery.stop();
+                }
+                return Err(e);
+            }
+        };
+
+        *self.sender.lock().unwrap() = Some(send_sock);
+        self.live.clear();
+        self.feedback.resync();
+        feedback::spawn_publisher(app.clone(), cancel.clone());
+        runtime.cancel = Some(cancel);
+        runtime.receiver = Some(receiver);
+        runtime.oscquery = oscquery;
+        let address = match runtime.oscquery.as_ref() {
+            Some(oscquery) => format!("{} (OSCQuery on {})", receive_addr, oscquery.http_addr),
+            None => receive_addr.to_string()
+        };
+        drop(runtime);
+
+        self.status.started(app, address);
+        let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Listening to {}", receive_addr) });
+        println!("Listening to {}", receive_addr);
+        self.send_strength(QTSInteraction::SHOCK, *crate::QTSHOCK_SHK_STRENGTH.lock().unwrap());
+        self.send_strength(QTSInteraction::VIBRATE, *crate::QTSHOCK_VIB_STRENGTH.lock().unwrap());
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        let (cancel, receiver, oscquery) = {
+            let mut runtime = self.runtime.lock().unwrap();
+            (runtime.cancel.take(), runtime.receiver.take(), runtime.oscquery.take())
+        };
+        if let Some(cancel) = cancel {
+            cancel.cancel();
+        }
+        if let Some(oscquery) = oscquery {
+            oscquery.stop();
+        }
+        if let Some(receiver) = receiver {
+            // An OSC panic stops us from the receiver thread itself, it exits once the packet is handled
+            if receiver.thread().id() != thread::current().id() {
+                let _ = receiver.join();
+            }
+        }
+        *self.sender.lock().unwrap() = None;
+        *self.vrchat_addr.lock().unwrap() = None;
+    }
+
+    pub fn send_parameter(&self, name: &str, value: OscType) -> Result<(), String> {
+        self.send_message(&format!("/avatar/parameters/{}", name), vec![value])
+    }
+
+    pub fn send_message(&self, addr: &str, args: Vec<OscType>) -> Result<(), String> {
+        let sender = self.sender.lock().unwrap();
+        let sock = match sender.as_ref() {
+            Some(sock) => sock,
+            None => {
+                // Nothing to tell VRChat while the integration is off
+                return Ok(());
+            }
+        };
+        let to_addr = self.send_addr();
+        let msg_buf = encoder::encode(&OscPacket::Message(OscMessage {
+            addr: addr.to_string(),
+            args,
+        }))
+        .map_err(|e| format!("Couldn't encode {}: {}", addr, e))?;
+        sock.send_to(&msg_buf, to_addr).map_err(|e| format!("Couldn't send {}: {}", addr, e))?;
+        Ok(())
+    }
+
+    // Tells the room what a trigger did, `custom` is the firing mapping's own template
+    pub fn notify_chatbox(&self, result: &Result<Option<SafetyVerdict>, DeviceError>, action: &QTSAction, target: &str, custom: Option<&str>) {
+        let config = self.chatbox_config();
+        if !config.enabled {
+            return;
+        }
+        let message = match config.message(result, action, target, custom) {
+            Some(message) => message,
+            None => {
+                return;
+            }
+        };
+        if !self.chatbox_limiter.allow(config.interval_ms) {
+            return;
+        }
+        // Sent straight to the chatbox instead of opening the keyboard
+        let args = vec![OscType::String(message), OscType::Bool(true), OscType::Bool(config.sound)];
+        if let Err(e) = self.send_message(CHATBOX_ADDRESS, args) {
+            println!("{}", e);
+        }
+    }
+
+    // Mirrors a strength setting onto the avatar so in-game menus match the app
+    pub fn send_strength(&self, interaction: QTSInteraction, strength: u8) {
+        let name = match interaction {
+            QTSInteraction::SHOCK => SHOCK_STRENGTH_PARAMETER,
+            QTSInteraction::VIBRATE => VIBRATE_STRENGTH_PARAMETER,
+            QTSInteraction::BEEP => {
+                return;
+            }
+        };
+        if let Err(e) = self.send_parameter(name, feedback::strength_value(strength)) {
+            println!("{}", e);
+        }
+    }
+}
+
+
+fn receive_loop(app: AppHandle, sock: UdpSocket, mut filter: SourceFilter, ctx: OscContext) {
+    let cancel = ctx.cancel.clone();
+    let status = &app.state::<VrcService>().inner().status;
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    let mut failed = false;
+
+    while !cancel.is_cancelled() {
+        match sock.recv_from(&mut buf) {
+            Ok((size, addr)) => {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                if let Err(rejection) = filter.check(addr.ip()) {
+                    status.dropped(&app);
+                    if filter.first_rejection(addr.ip(), rejection) {
+                        println!("Dropping OSC from {}: it {}", addr, rejection);
+                        let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Ignoring OSC from {}, it {}", addr.ip(), rejection) });
+                    }
+                    continue;
+                }
+                status.packet(&app);
+                // Recorded before decoding so malformed packets can be reproduced too
+                if let Err(e) = app.state::<VrcService>().record(addr, &buf[..size]) {
+                    println!("Stopped recording: {}", e);
+                    let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Stopped recording: {}", e) });
+                }
+                let packet = match rosc::decoder::decode_udp(&buf[..size]) {
+                    Ok((_, packet)) => packet,
+                    Err(e) => {
+                        println!("Ignoring malformed OSC packet from {}: {}", addr, e);
+                        status.malformed(&app, format!("Malformed OSC packet from {}: {}", addr, e));
+                        continue;
+                    }
+                };
+                block_on(handle_packet(&app, &ctx, packet));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                continue;
+            }
+            Err(e) if is_bad_datagram(&e) => {
+                println!("Ignoring bad OSC datagram: {}", e);
+                status.malformed(&app, format!("Bad OSC datagram: {}", e));
+                continue;
+            }
+            Err(e) => {
+                println!("Error receiving from socket: {}", e);
+                let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Error receiving from VRChat: {}", e) });
+                status.failed(&app, format!("Error receiving from VRChat: {}", e));
+                failed = true;
+                break;
+            }
+        }
+    }
+    // Marks the service as stopped if the socket failed on its own
+    cancel.cancel();
+    if !failed {
+        status.stopped(&app);
+    }
+    println!("VRC OSC Socket closed!");
+    let _ = app.emit_all("vrc-osc-event", Payload { message: "VRC OSC Socket closed".into() });
+}
+
+/*
+    Errors that are about a single datagram rather than the socket. Windows reports an ICMP
+    port unreachable for an earlier send as a reset on the next receive.
+*/
+fn is_bad_datagram(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused | ErrorKind::Interrupted)
+        || (cfg!(windows) && e.raw_os_error() == Some(WSAEMSGSIZE))
+}
+
+/*
+    Bundles are unpacked recursively. Messages in a bundle with a future timetag are
+    scheduled for that time, anything immediate or already due is handled straight away.
+*/
+pub async fn handle_packet(app: &AppHandle, ctx: &OscContext, packet: OscPacket) {
+    let mut messages = Vec::new();
+    unpack_packet(packet, None, &mut messages);
+    for (delay, msg) in messages {
+        match delay {
+            None => handle_message(app, ctx, msg).await,
+            // Never hold back a panic button
+            Some(_) if emergency::EMERGENCY_OSC_ADDRESSES.contains(&msg.addr.as_str()) => handle_message(app, ctx, msg).await,
+            Some(delay) if delay > MAX_BUNDLE_DELAY => {
+                println!("Dropping OSC message {} scheduled {:?} ahead", msg.addr, delay);
+            },
+            Some(delay) => {
+                let app = app.clone();
+                let ctx = ctx.clone();
+                tauri::async_runtime::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    // The integration or replay may have stopped in the meantime
+                    if ctx.is_running() {
+                        handle_message(&app, &ctx, msg).await;
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn unpack_packet(packet: OscPacket, delay: Option<Duration>, messages: &mut Vec<(Option<Duration>, OscMessage)>) {
+    match packet {
+        OscPacket::Message(msg) => messages.push((delay, msg)),
+        OscPacket::Bundle(bundle) => {
+            // A nested bundle marked immediate still waits for its parent
+            let delay = bundle_delay(&bundle.timetag).or(delay);
+            for content in bundle.content {
+                unpack_packet(content, delay, messages);
+            }
+        }
+    }
+}
+
+// How long until a timetag is due, None when it's immediate or already passed
+fn bundle_delay(timetag: &OscTime) -> Option<Duration> {
+    if timetag.seconds == 0 && timetag.fractional == 1 {
+        return None;
+    }
+    let seconds = (timetag.seconds as u64).checked_sub(NTP_UNIX_OFFSET)?;
+    let nanos = ((timetag.fractional as u64) * 1_000_000_000) >> 32;
+    let due = UNIX_EPOCH + Duration::new(seconds, nanos as u32);
+    due.duration_since(SystemTime::now()).ok()
+}
+
+// An argument that isn't what its address needs, counted as malformed
+#[derive(Debug)]
+struct BadValue {
+    value: Option<OscType>,
+    expected: &'static str
+}
+
+impl BadValue {
+    fn new(value: Option<&OscType>, expected: &'static str) -> Self {
+        Self { value: value.cloned(), expected }
+    }
+}
+
+// A QTS trigger with the value its type needs
+#[derive(Debug)]
+enum QtsTrigger {
+    Push(f32),
+    Prop(f32),
+    Hit(bool)
+}
+
+// A QTS address and what its value turned out to be
+#[derive(Debug)]
+struct QtsParameter {
+    address: QtsAddress,
+    trigger: Result<QtsTrigger, BadValue>
+}
+
+/*
+    What a message is, read from its address and first argument without touching the app or
+    any state. handle_message acts on it, the receive tests fuzz this same reading.
+*/
+#[derive(Debug)]
+enum Incoming {
+    Emergency { pressed: bool },
+    AvatarChange(Result<String, BadValue>),
+    // The in-game strength radials
+    Strength(QTSInteraction, Result<f32, BadValue>),
+    // Mappings get the first look, `qts` only counts when none of them use the address
+    Parameter { builtin: Option<VrcBuiltin>, qts: Result<QtsParameter, QtsAddressError> }
+}
+
+fn read_float(value: Option<&OscType>) -> Result<f32, BadValue> {
+    match value {
+        Some(OscType::Float(f)) => Ok(*f),
+        other => Err(BadValue::new(other, "a float"))
+    }
+}
+
+fn read_message(msg: &OscMessage) -> Incoming {
+    let value = msg.args.first();
+    if emergency::EMERGENCY_OSC_ADDRESSES.contains(&msg.addr.as_str()) {
+        let pressed = match value {
+            Some(OscType::Bool(b)) => *b,
+            Some(OscType::Float(f)) => *f > 0.5f32,
+            Some(OscType::Int(i)) => *i != 0,
+            _ => true
+        };
+        return Incoming::Emergency { pressed };
+    }
+    if msg.addr == AVATAR_CHANGE_ADDRESS {
+        return Incoming::AvatarChange(match value {
+            Some(OscType::String(avatar)) => Ok(avatar.clone()),
+            other => Err(BadValue::new(other, "a string"))
+        });
+    }
+    let parameter = msg.addr.strip_prefix("/avatar/parameters/");
+    match parameter {
+        Some(SHOCK_STRENGTH_PARAMETER) => {
+            return Incoming::Strength(QTSInteraction::SHOCK, read_float(value));
+        },
+        Some(VIBRATE_STRENGTH_PARAMETER) => {
+            return Incoming::Strength(QTSInteraction::VIBRATE, read_float(value));
+        },
+        _ => {}
+    }
+    let qts = parse_qts_address(&msg.addr).map(|address| {
+        let trigger = match address.osc_type {
+            QTSOSCType::PUSH => read_float(value).map(QtsTrigger::Push),
+            QTSOSCType::PROP => read_float(value).map(QtsTrigger::Prop),
+            QTSOSCType::HIT => match value {
+                Some(OscType::Bool(b)) => Ok(QtsTrigger::Hit(*b)),
+                other => Err(BadValue::new(other, "a bool"))
+            }
+        };
+        QtsParameter { address, trigger }
+    });
+    Incoming::Parameter { builtin: parameter.and_then(VrcBuiltin::from_parameter), qts }
+}
+
+async fn handle_message(app: &AppHandle, ctx: &OscContext, msg: OscMessage) {
+    let vrc = app.state::<VrcService>();
+    match read_message(&msg) {
+        Incoming::Emergency { pressed } => {
+            vrc.status.handled();
+            if pressed {
+                match &ctx.dry_run {
+                    Some(dry_run) => dry_run.report(format!("Emergency stop from {}", msg.addr)),
+                    None => emergency::engage(app, "OSC", true).await
+                }
+            }
+        },
+        Incoming::AvatarChange(Err(bad)) => {
+            bad_value(app, &msg.addr, &bad);
+            app.emit_all("vrc-osc-event", Payload { message: format!("Invalid avatar change from VRChat. Bad value type.") }).unwrap();
+        },
+        Incoming::AvatarChange(Ok(avatar)) => {
+            change_avatar(app, ctx, avatar);
+        },
+        Incoming::Strength(interaction, value) => {
+            // Someone else's avatar can use the same parameter names
+            if let AvatarGate::Blocked(_) = vrc.avatar_gate(&ctx.state) {
+                return;
+            }
+            // A dry run mustn't change the stored strengths
+            match (&ctx.dry_run, value) {
+                (Some(dry_run), _) => dry_run.report(format!("{:?} strength from VRChat: {:?}", interaction, msg.args.first())),
+                (None, Ok(value)) => receive_strength(app, interaction, value),
+                (None, Err(bad)) => {
+                    bad_value(app, &msg.addr, &bad);
+                    app.emit_all("vrc-osc-event", Payload { message: format!("Invalid strength from VRChat. Bad value type.") }).unwrap();
+                }
+            }
+        },
+        Incoming::Parameter { builtin, qts } => {
+            // Recorded for mapping gates, mappings on these addresses still run
+            if let (Some(builtin), Some(value)) = (builtin, msg.args.first()) {
+                ctx.state.builtins.update(builtin, value);
+            }
+            handle_parameter(app, ctx, &msg, qts).await;
+        }
+    }
+}
+
+fn change_avatar(app: &AppHandle, ctx: &OscContext, avatar: String) {
+    let vrc = app.state::<VrcService>();
+    println!("Avatar changed to {}, resyncing parameters", avatar);
+    vrc.status.handled();
+    *ctx.state.avatar.lock().unwrap() = Some(avatar.clone());
+    // Nothing held on the old avatar can be released on the new one
+    ctx.state.clear();
+    let message = match vrc.avatar_gate(&ctx.state) {
+        AvatarGate::Open => format!("Avatar changed to {}", avatar),
+        AvatarGate::Allowed(profile) if !profile.name.is_empty() => format!("Avatar changed to {} ({}), triggers are on", profile.name, avatar),
+        AvatarGate::Allowed(_) => format!("Avatar changed to {}, triggers are on", avatar),
+        AvatarGate::Blocked(reason) => format!("{}, triggers are off", reason)
+    };
+    app.emit_all("vrc-osc-event", Payload { message }).unwrap();
+    // A replayed change doesn't change what's worn in VRChat
+    if !ctx.replay {
+        vrc.feedback.resync();
+        vrc.send_strength(QTSInteraction::SHOCK, *crate::QTSHOCK_SHK_STRENGTH.lock().unwrap());
+        vrc.send_strength(QTSInteraction::VIBRATE, *crate::QTSHOCK_VIB_STRENGTH.lock().unwrap());
+    }
+}
+
+async fn handle_parameter(app: &AppHandle, ctx: &OscContext, msg: &OscMessage, qts: Result<QtsParameter, QtsAddressError>) {
+    let vrc = app.state::<VrcService>();
+    // Someone else's avatar can use the same parameter names
+    let profile = match vrc.avatar_gate(&ctx.state) {
+        AvatarGate::Open => None,
+        AvatarGate::Allowed(profile) => Some(profile),
+        AvatarGate::Blocked(_) => {
+            return;
+        }
+    };
+    // A mapped address is the user's to define, even if it also looks like a QTS_ parameter
+    if handle_mappings(app, ctx, msg, profile.as_ref()).await {
+        return;
+    }
+    let QtsParameter { address, trigger } = match qts {
+        Ok(qts) => qts,
+        Err(QtsAddressError::NotQts) | Err(QtsAddressError::Reserved(_)) => {
+            return;
+        },
+        Err(e) => {
+            app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC address {}: {}", msg.addr, e) }).unwrap();
+            return;
+        }
+    };
+    app.emit_all("vrc-osc-event", Payload { message: format!("VRC OSC msg | {}: {:?}", msg.addr, msg.args).into() }).unwrap();
+    vrc.status.handled();
+    let target: &str = &address.target;
+    let strength = address.strength;
+
+    // Bare words that aren't interactions are pattern names from older avatars
+    let qt_osc_action: QTSAction = match address.action {
+        QTSAction::Pattern(name) => match patterns::find_pattern(&name) {
+            Some(pattern) => QTSAction::Pattern(pattern.name),
+            None => {
+                app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. No pattern named '{}'.", name).into() }).unwrap();
+                return;
+            }
+        },
+        action => action
+    };
+    let trigger = match trigger {
+        Ok(trigger) => trigger,
+        Err(bad) => {
+            bad_value(app, &msg.addr, &bad);
+            let problem = if bad.value.is_some() { "Bad value type" } else { "No value" };
+            app.emit_all("vrc-osc-event", Payload { message: format!("Invalid QTShock OSC data received. {}.", problem) }).unwrap();
+            return;
+        }
+    };
+    let scope = ctx.scope(profile.as_ref());
+
+    match trigger {
+        QtsTrigger::Push(f) => {
+            let hysteresis = vrc.hysteresis_for(&msg.addr);
+            match ctx.state.latches.update(&msg.addr, f, &hysteresis) {
+                LatchEvent::Fire => {
+                    run_trigger(app, target, &qt_osc_action, strength, &scope, Some("Boop"), None).await;
+                },
+                LatchEvent::Hold(hold) => {
+                    let app = app.clone();
+                    let ctx = ctx.clone();
+                    let scope = scope.clone();
+                    let addr = msg.addr.clone();
+                    let target = target.to_string();
+                    tauri::async_runtime::spawn(async move {
+                        tokio::time::sleep(Duration::from_millis(hysteresis.min_hold_ms as u64)).await;
+                        if ctx.is_running() && ctx.state.latches.confirm(&addr, hold) {
+                            run_trigger(&app, &target, &qt_osc_action, strength, &scope, Some("Boop"), None).await;
+                        }
+                    });
+                },
+                LatchEvent::Release => {
+                    app.emit_all("vrc-osc-event", Payload { message: format!("Unboop").into() }).unwrap();
+                },
+                LatchEvent::Nothing => {}
+            }
+        },
+        QtsTrigger::Prop(f) => {
+            let mut proportional = vrc.proportional_for(&msg.addr, strength);
+            // Scaled within the cap rather than cut off at it
+            if let Some(cap) = scope.caps.for_action(&qt_osc_action) {
+                proportional.max_strength = proportional.max_strength.min(cap);
+                proportional.min_strength = proportional.min_strength.min(cap);
+            }
+            match ctx.state.latches.update(&msg.addr, f, &proportional.trigger) {
+                LatchEvent::Fire => {
+                    start_proportional(app.clone(), ctx.clone(), msg.addr.clone(), target.to_string(), qt_osc_action, proportional, scope);
+                },
+                LatchEvent::Hold(hold) => {
+                    let app = app.clone();
+                    let ctx = ctx.clone();
+                    let addr = msg.addr.clone();
+                    let target = target.to_string();
+                    tauri::async_runtime::spawn(async move {
+                        tokio::time::sleep(Duration::from_millis(proportional.trigger.min_hold_ms as u64)).await;
+                        if ctx.is_running() && ctx.state.latches.confirm(&addr, hold) {
+                            start_proportional(app, ctx, addr, target, qt_osc_action, proportional, scope);
+                        }
+                    });
+                },
+                LatchEvent::Release => {
+                    app.emit_all("vrc-osc-event", Payload { message: format!("Unboop").into() }).unwrap();
+                },
+                LatchEvent::Nothing => {}
+            }
+        },
+        QtsTrigger::Hit(b) => {
+            app.emit_all("vrc-osc-event", Payload { message: format!("HIT!!!!!!!!!!!!!!!!!!!!").into() }).unwrap();
+            if b {
+                run_trigger(app, target, &qt_osc_action, strength, &scope, None, None).await;
+            }
+        }
+    }
+}
+
+// Arguments of the wrong type are counted as malformed, like packets that don't decode
+fn bad_value(app: &AppHandle, addr: &str, bad: &BadValue) {
+    let error = match &bad.value {
+        Some(value) => format!("OSC message {} has {:?}, expected {}", addr, value, bad.expected),
+        None => format!("OSC message {} has no value", addr)
+    };
+    app.state::<VrcService>().status.malformed(app, error);
+}
+
+/*
+    The in-game radial changed a strength. It's stored like a change from the app, shocks are
+    capped to the safety limit first and the avatar is corrected if the cap kicked in.
+*/
+fn receive_strength(app: &AppHandle, interaction: QTSInteraction, value: f32) {
+    let vrc = app.state::<VrcService>();
+    vrc.status.handled();
+    // Inverse of feedback::strength_value
+    let requested = ((value * 100.0).round() + 1.0).clamp(MIN_STRENGTH as f32, MAX_STRENGTH as f32) as u8;
+    let strength = match interaction {
+        QTSInteraction::SHOCK => requested.min(QTSHOCK_SAFETY.lock().unwrap().limits().max_strength),
+        _ => requested
+    };
+    let stored = match interaction {
+        QTSInteraction::SHOCK => &crate::QTSHOCK_SHK_STRENGTH,
+        _ => &crate::QTSHOCK_VIB_STRENGTH
+    };
+    let changed = {
+        let mut stored = stored.lock().unwrap();
+        let changed = *stored != strength;
+        *stored = strength;
+        changed
+    };
+    if strength != requested {
+        app.emit_all("vrc-osc-event", Payload { message: format!("{:?} strength {} from VRChat capped to {}", interaction, requested, strength) }).unwrap();
+        vrc.send_strength(interaction, strength);
+    }
+    if changed {
+        app.emit_all("vrc-osc-event", Payload { message: format!("{:?} strength set to {} from VRChat", interaction, strength) }).unwrap();
+        settings::changed();
+    }
+}
+
+// What a mapping makes of a value, Hold carries the latch's hold and how long to wait for it
+enum MappingEvent {
+    Fire,
+    Hold(u64, u64),
+    Nothing,
+    BadValue(String)
+}
+
+// Only touches the latch and edge state, so the receive tests run it as is
+fn mapping_event(state: &OscState, mapping: &OscMapping, addr: &str, value: &OscType) -> MappingEvent {
+    let key = mapping.state_key(addr);
+    match &mapping.condition {
+        MappingCondition::Threshold { trigger } => {
+            let f = match value {
+                OscType::Float(f) => *f,
+                _ => {
+                    return MappingEvent::BadValue(format!("Mapping '{}' expects a float from {}", mapping.name, addr));
+                }
+            };
+            match state.latches.update(&key, f, trigger) {
+                LatchEvent::Fire => MappingEvent::Fire,
+                LatchEvent::Hold(hold) => MappingEvent::Hold(hold, trigger.min_hold_ms as u64),
+                LatchEvent::Release | LatchEvent::Nothing => MappingEvent::Nothing
+            }
+        },
+        condition => {
+            match state.edges.update(&key, condition, value) {
+                EdgeResult::Fire => MappingEvent::Fire,
+                EdgeResult::BadValue => MappingEvent::BadValue(format!("Mapping '{}' can't use {:?} from {}", mapping.name, value, addr)),
+                EdgeResult::Nothing => MappingEvent::Nothing
+            }
+        }
+    }
+}
+
+// Evaluates every mapping matching the address that the worn avatar uses, returns false if none did
+async fn handle_mappings(app: &AppHandle, ctx: &OscContext, msg: &OscMessage, profile: Option<&AvatarProfile>) -> bool {
+    let vrc = app.state::<VrcService>();
+    let mappings: Vec<OscMapping> = vrc.mappings().into_iter()
+        .filter(|m| m.matches(&msg.addr) && profile.map_or(true, |profile| profile.uses_mapping(m)))
+        .collect();
+    if mappings.is_empty() {
+        return false;
+    }
+    let scope = ctx.scope(profile);
+    vrc.status.handled();
+    let value = match msg.args.first() {
+        Some(value) => value,
+        None => {
+            bad_value(app, &msg.addr, &BadValue::new(None, "a value"));
+            app.emit_all("vrc-osc-event", Payload { message: format!("Invalid OSC data for {}. No value.", msg.addr) }).unwrap();
+            return true;
+        }
+    };
+
+    let mut bad = false;
+    for mapping in mappings {
+        let fired = format!("Mapping '{}' fired", mapping.name);
+        match mapping_event(&ctx.state, &mapping, &msg.addr, value) {
+            MappingEvent::Fire => {
+                fire_mapping(app, ctx, &mapping, &fired, &scope).await;
+            },
+            MappingEvent::Hold(hold, hold_ms) => {
+                let app = app.clone();
+                let ctx = ctx.clone();
+                let scope = scope.clone();
+                let key = mapping.state_key(&msg.addr);
+                tauri::async_runtime::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(hold_ms)).await;
+                    if ctx.is_running() && ctx.state.latches.confirm(&key, hold) {
+                        fire_mapping(&app, &ctx, &mapping, &fired, &scope).await;
+                    }
+                });
+            },
+            MappingEvent::BadValue(message) => {
+                bad = true;
+                app.emit_all("vrc-osc-event", Payload { message }).unwrap();
+            },
+            MappingEvent::Nothing => {}
+        }
+    }
+    // Counted once however many mappings couldn't use it
+    if bad {
+        bad_value(app, &msg.addr, &BadValue::new(Some(value), "a value its mappings can use"));
+    }
+    true
+}
+
+/*
+    Gates are checked when the mapping actually fires, after any hold time. The avatar's caps
+    go on the gated action, a shock turned into a vibration is capped like a vibration.
+*/
+async fn fire_mapping(app: &AppHandle, ctx: &OscContext, mapping: &OscMapping, fired: &str, scope: &TriggerScope) {
+    let (action, strength) = match mapping.gated_action(&ctx.state.builtins) {
+        Ok(gated) => gated,
+        Err(reason) => {
+            app.emit_all("vrc-osc-event", Payload { message: reason }).unwrap();
+            return;
+        }
+    };
+    run_trigger(app, &mapping.target, &action, strength, scope, Some(fired), mapping.chatbox.as_deref()).await;
+}
+
+/*
+    Fires a latched PROP parameter at the strength its value maps to. In continuous mode it
+    keeps going at the latest value until the parameter is released, patterns only fire once.
+*/
+fn start_proportional(app: AppHandle, ctx: OscContext, addr: String, target: String, action: QTSAction, proportional: Proportional, scope: TriggerScope) {
+    tauri::async_runtime::spawn(async move {
+        let interaction = match action {
+            QTSAction::Interaction(interaction) if proportional.continuous => interaction,
+            _ => {
+                if let Some(value) = ctx.state.latches.held_value(&addr) {
+                    run_trigger(&app, &target, &action, Some(proportional.strength_for(value)), &scope, Some("Boop"), None).await;
+                }
+                return;
+            }
+        };
+        let mut first = true;
+        loop {
+            let vrc = app.state::<VrcService>();
+            let value = match ctx.state.latches.held_value(&addr) {
+                Some(value) if ctx.is_running() => value,
+                _ => break
+            };
+            let strength = proportional.strength_for(value);
+            let result = crate::trigger_qtshock(&app, &target, interaction, Some(strength), Some(proportional.interval_ms), &scope).await;
+            if first && scope.dry_run.is_none() {
+                let result = result.clone().map(Some);
+                vrc.notify_chatbox(&result, &action, &target, None);
+            }
+            match result {
+                Ok(verdict) => {
+                    if first {
+                        app.emit_all("vrc-osc-event", Payload { message: format!("Boop").into() }).unwrap();
+                    }
+                    if let Some(reason) = verdict.reason {
+                        app.emit_all("vrc-osc-event", Payload { message: reason }).unwrap();
+                    }
+                },
+                Err(e) => {
+                    vrc.status.error(&app, e.to_string());
+                    app.emit_all("vrc-osc-event", Payload { message: format!("Something went wrong when triggering your QTShock: {}", e).into() }).unwrap();
+                    if let DeviceError::Blocked(_) | DeviceError::EmergencyStop = e {
+                        break;
+                    }
+                }
+            }
+            first = false;
+            tokio::time::sleep(Duration::from_millis(proportional.interval_ms as u64)).await;
+        }
+    });
+}
+
+/*
+    Fires a trigger and reports the outcome to the console and the chatbox, `fired` is logged
+    when it went through and `chatbox` is a mapping's own chatbox template. A dry run keeps
+    the chatbox quiet, nothing really happened.
+*/
+async fn run_trigger(app: &AppHandle, target: &str, action: &QTSAction, strength: Option<u8>, scope: &TriggerScope, fired: Option<&str>, chatbox: Option<&str>) {
+    let vrc = app.state::<VrcService>();
+    let result = crate::trigger_action(app, target, action, strength, scope).await;
+    if scope.dry_run.is_none() {
+        vrc.notify_chatbox(&result, action, target, chatbox);
+    }
+    match result {
+        Ok(verdict) => {
+            if let Some(fired) = fired {
+                app.emit_all("vrc-osc-event", Payload { message: fired.to_string() }).unwrap();
+            }
+            if let Some(reason) = verdict.and_then(|verdict| verdict.reason) {
+                app.emit_all("vrc-osc-event", Payload { message: reason }).unwrap();
+            }
+        },
+        Err(e) => {
+            vrc.status.error(app, e.to_string());
+            app.emit_all("vrc-osc-event", Payload { message: format!("Something went wrong when triggering your QTShock: {}", e).into() }).unwrap();
+        }
+    }
+}
+
+
+// Shared by the toggle and the auto-start on launch
+pub fn set_enabled(app: &AppHandle, start: bool) -> Result<(), String> {
+    if start && emergency::is_active() {
+        let message = "Emergency stop is active, re-arm before enabling the VRChat integration.".to_string();
+        let _ = app.emit_all("vrc-osc-event", Payload { message: message.clone() });
+        return Err(message);
+    }
+    let vrc = app.state::<VrcService>();
+    if !start {
+        vrc.stop();
+        settings::set_integration(Some(false), None);
+        return Ok(());
+    }
+    if let Err(e) = vrc.start(app) {
+        vrc.status.failed(app, e.clone());
+        let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Something went wrong when starting the VRChat integration. {}", e) });
+        return Err(e);
+    }
+    settings::set_integration(Some(true), None);
+    let _ = block_on(crate::beep(app.clone(), String::new(), None));
+    Ok(())
+}
+
+// Picks up new addresses if the integration is running, leaves it off otherwise
+pub fn restart(app: &AppHandle) -> Result<(), String> {
+    let vrc = app.state::<VrcService>();
+    if !vrc.is_running() {
+        return Ok(());
+    }
+    vrc.stop();
+    if let Err(e) = vrc.start(app) {
+        vrc.status.failed(app, e.clone());
+        let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Couldn't restart the VRChat integration with the new addresses. {}", e) });
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn start_vrc_osc(app: AppHandle, start: bool) -> Result<(), String> {
+    set_enabled(&app, start)
+}
+
+#[tauri::command]
+pub fn get_osc_config(vrc: State<'_, VrcService>) -> OscConfig {
+    vrc.osc_config()
+}
+
+#[tauri::command]
+pub fn get_osc_triggers(vrc: State<'_, VrcService>) -> OscTriggers {
+    vrc.triggers()
+}
+
+#[tauri::command]
+pub fn set_osc_triggers(vrc: State<'_, VrcService>, triggers: OscTriggers) -> Result<OscTriggers, String> {
+    triggers.validate()?;
+    vrc.set_triggers(triggers.clone());
+    settings::changed();
+    Ok(triggers)
+}
+
+#[tauri::command]
+pub fn get_osc_mappings(vrc: State<'_, VrcService>) -> Vec<OscMapping> {
+    vrc.mappings()
+}
+
+// Replaces the mapping with the same name, or adds it
+#[tauri::command]
+pub fn save_osc_mapping(vrc: State<'_, VrcService>, mapping: OscMapping) -> Result<Vec<OscMapping>, String> {
+    mapping.validate()?;
+    devices::resolve_target(&mapping.target)?;
+    if let QTSAction::Pattern(name) = &mapping.action {
+        if patterns::find_pattern(name).is_none() {
+            return Err(format!("No pattern named '{}'", name));
+        }
+    }
+    let mut mappings = vrc.mappings();
+    match mappings.iter_mut().find(|m| m.name.eq_ignore_ascii_case(&mapping.name)) {
+        Some(existing) => *existing = mapping,
+        None => mappings.push(mapping)
+    }
+    vrc.set_mappings(mappings.clone());
+    settings::changed();
+    Ok(mappings)
+}
+
+#[tauri::command]
+pub fn delete_osc_mapping(vrc: State<'_, VrcService>, name: String) -> Result<Vec<OscMapping>, String> {
+    let mut mappings = vrc.mappings();
+    let count = mappings.len();
+    mappings.retain(|m| !m.name.eq_ignore_ascii_case(&name));
+    if mappings.len() == count {
+        return Err(format!("No mapping named '{}'", name));
+    }
+    vrc.set_mappings(mappings.clone());
+    settings::changed();
+    Ok(mappings)
+}
+
+#[tauri::command]
+pub fn get_avatar_profiles(vrc: State<'_, VrcService>) -> Vec<AvatarProfile> {
+    vrc.avatar_profiles()
+}
+
+#[tauri::command]
+pub fn get_current_avatar(vrc: State<'_, VrcService>) -> Option<String> {
+    vrc.avatar()
+}
+
+// Replaces the profile for the same avatar, or adds it
+#[tauri::command]
+pub fn save_avatar_profile(vrc: State<'_, VrcService>, profile: AvatarProfile) -> Result<Vec<AvatarProfile>, String> {
+    profile.validate()?;
+    let mut profiles = vrc.avatar_profiles();
+    match profiles.iter_mut().find(|p| p.id == profile.id) {
+        Some(existing) => *existing = profile,
+        None => profiles.push(profile)
+    }
+    vrc.set_avatar_profiles(profiles.clone());
+    settings::changed();
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub fn delete_avatar_profile(vrc: State<'_, VrcService>, id: String) -> Result<Vec<AvatarProfile>, String> {
+    let mut profiles = vrc.avatar_profiles();
+    let count = profiles.len();
+    profiles.retain(|p| p.id != id);
+    if profiles.len() == count {
+        return Err(format!("No profile for avatar {}", id));
+    }
+    vrc.set_avatar_profiles(profiles.clone());
+    settings::changed();
+    Ok(profiles)
+}
+
+// Records incoming OSC to a file picked by the user, returns where or None if they cancelled
+#[tauri::command]
+pub fn start_osc_recording(vrc: State<'_, VrcService>) -> Result<Option<String>, String> {
+    let path = FileDialog::new()
+        .set_title("Save the OSC recording")
+        .add_filter("OSC recording", &["jsonl"])
+        .set_file_name("osc-recording.jsonl")
+        .save_file();
+    let path = match path {
+        Some(path) => path,
+        None => {
+            return Ok(None);
+        }
+    };
+    let recorder = Recorder::create(&path)?;
+    if let Some(previous) = vrc.recorder.lock().unwrap().replace(recorder) {
+        previous.finish();
+    }
+    println!("Recording OSC to {}", path.display());
+    Ok(Some(path.display().to_string()))
+}
+
+#[tauri::command]
+pub fn stop_osc_recording(vrc: State<'_, VrcService>) -> Option<String> {
+    let recorder = vrc.recorder.lock().unwrap().take()?;
+    Some(recorder.finish().display().to_string())
+}
+
+/*
+    Replays a recording picked by the user. `speed` multiplies the recorded timing, 0 is as
+    fast as possible. With `dry_run` nothing reaches the devices, see recording.rs.
+*/
+#[tauri::command]
+pub fn replay_osc_recording(app: AppHandle, speed: f32, dry_run: bool) -> Result<bool, String> {
+    recording::check_speed(speed)?;
+    let vrc = app.state::<VrcService>();
+    if vrc.replay.lock().unwrap().is_some() {
+        return Err("A recording is already being replayed".to_string());
+    }
+    if !dry_run {
+        emergency::check()?;
+    }
+    let path = FileDialog::new()
+        .set_title("Open an OSC recording")
+        .add_filter("OSC recording", &["jsonl"])
+        .pick_file();
+    let path = match path {
+        Some(path) => path,
+        None => {
+            return Ok(false);
+        }
+    };
+    let packets = recording::load(&path)?;
+    let cancel = CancellationToken::new();
+    {
+        let mut replay = vrc.replay.lock().unwrap();
+        if replay.is_some() {
+            return Err("A recording is already being replayed".to_string());
+        }
+        *replay = Some(cancel.clone());
+    }
+    let _ = app.emit_all("vrc-osc-event", Payload { message: format!("Replaying {} packets from {}{}", packets.len(), path.display(), if dry_run { " (dry run)" } else { "" }) });
+    recording::start_replay(&app, packets, speed, dry_run, cancel);
+    Ok(true)
+}
+
+#[tauri::command]
+pub fn stop_osc_replay(vrc: State<'_, VrcService>) {
+    if let Some(cancel) = vrc.replay.lock().unwrap().as_ref() {
+        cancel.cancel();
+    }
+}
+
+#[tauri::command]
+pub fn get_chatbox_config(vrc: State<'_, VrcService>) -> ChatboxConfig {
+    vrc.chatbox_config()
+}
+
+#[tauri::command]
+pub fn set_chatbox_config(vrc: State<'_, VrcService>, config: ChatboxConfig) -> Result<ChatboxConfig, String> {
+    config.validate()?;
+    vrc.set_chatbox_config(config.clone());
+    settings::changed();
+    Ok(config)
+}
+
+// Restarts a running integration so the new addresses are used straight away
+#[tauri::command]
+pub fn set_osc_config(app: AppHandle, config: OscConfig) -> Result<OscConfig, String> {
+    config.validate()?;
+    let vrc = app.state::<VrcService>();
+    if vrc.osc_config() == config {
+        return Ok(config);
+    }
+    vrc.set_osc_config(config.clone());
+    settings::changed();
+    restart(&app)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use rosc::OscBundle;
+
+    const ROUNDS: usize = 20_000;
+
+    fn mapping(name: &str, condition: MappingCondition) -> OscMapping {
+        OscMapping {
+            name: name.to_string(),
+            enabled: true,
+            address: "/*".to_string(),
+            condition,
+            action: QTSAction::Interaction(QTSInteraction::VIBRATE),
+            target: String::new(),
+            strength: None,
+            chatbox: None,
+            gates: Vec::new()
+        }
+    }
+
+    // The receive path short of the app and the devices, read_message and mapping_event are what handle_message runs
+    fn receive(bytes: &[u8]) {
+        let packet = match rosc::decoder::decode_udp(bytes) {
+            Ok((_, packet)) => packet,
+            Err(_) => {
+                return;
+            }
+        };
+        let mut messages = Vec::new();
+        unpack_packet(packet, None, &mut messages);
+        let state = OscState::new(None);
+        let proportional = Proportional::default();
+        let mappings = [
+            mapping("bool", MappingCondition::BoolTrue),
+            mapping("threshold", MappingCondition::Threshold { trigger: Hysteresis { min_hold_ms: 100, ..Hysteresis::default() } }),
+            mapping("range", MappingCondition::Range { min: 0.2, max: 0.8 }),
+            mapping("int", MappingCondition::IntEquals { value: 1 })
+        ];
+        for (_, msg) in messages {
+            let incoming = read_message(&msg);
+            if let (Incoming::Parameter { builtin: Some(builtin), .. }, Some(value)) = (&incoming, msg.args.first()) {
+                state.builtins.update(*builtin, value);
+            }
+            if let Some(value) = msg.args.first() {
+                for mapping in mappings.iter().filter(|mapping| mapping.matches(&msg.addr)) {
+                    mapping_event(&state, mapping, &msg.addr, value);
+                }
+            }
+            if let Incoming::Parameter { qts: Ok(QtsParameter { trigger: Ok(QtsTrigger::Push(f) | QtsTrigger::Prop(f)), .. }), .. } = incoming {
+                state.latches.update(&msg.addr, f, &Hysteresis::default());
+                let strength = proportional.strength_for(f);
+                assert!((proportional.min_strength..=proportional.max_strength).contains(&strength), "{} mapped to {}", f, strength);
+            }
+        }
+    }
+
+    fn encode(packet: OscPacket) -> Vec<u8> {
+        encoder::encode(&packet).unwrap()
+    }
+
+    fn message(addr: &str, args: Vec<OscType>) -> OscPacket {
+        OscPacket::Message(OscMessage { addr: addr.to_string(), args })
+    }
+
+    // Well-formed packets to cut up, random bytes alone rarely get past the header
+    fn samples() -> Vec<Vec<u8>> {
+        let push = message("/avatar/parameters/QTS_0_PUSH_SHOCK", vec![OscType::Float(0.9)]);
+        let hit = message("/avatar/parameters/My_QTS_Collar_HIT_VIBRATE_40", vec![OscType::Bool(true)]);
+        let prop = message("/avatar/parameters/QTS_legs_PROP_SHOCK_30", vec![OscType::Float(f32::NAN)]);
+        let avatar = message(AVATAR_CHANGE_ADDRESS, vec![OscType::String("avtr_00000000".to_string())]);
+        let builtin = message("/avatar/parameters/AFK", vec![OscType::Int(1), OscType::Blob(vec![1, 2, 3])]);
+        let nested = OscPacket::Bundle(OscBundle {
+            timetag: OscTime { seconds: 0, fractional: 1 },
+            content: vec![push.clone(), hit.clone()]
+        });
+        let bundle = OscPacket::Bundle(OscBundle {
+            timetag: OscTime { seconds: u32::MAX, fractional: u32::MAX },
+            content: vec![nested, prop.clone(), avatar.clone()]
+        });
+        vec![encode(push), encode(hit), encode(prop), encode(avatar), encode(builtin), encode(bundle)]
+    }
+
+    #[test]
+    fn random_bytes_dont_panic() {
+        let mut rng = StdRng::seed_from_u64(24);
+        for _ in 0..ROUNDS {
+            let len = rng.gen_range(0..256);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            receive(&bytes);
+        }
+    }
+
+    #[test]
+    fn random_tails_on_valid_packets_dont_panic() {
+        let mut rng = StdRng::seed_from_u64(25);
+        let samples = samples();
+        for _ in 0..ROUNDS {
+            let sample = &samples[rng.gen_range(0..samples.len())];
+            let mut bytes = sample[..rng.gen_range(0..sample.len())].to_vec();
+            bytes.extend((0..rng.gen_range(0..64)).map(|_| rng.gen::<u8>()));
+            receive(&bytes);
+        }
+    }
+
+    #[test]
+    fn truncated_packets_dont_panic() {
+        for sample in samples() {
+            receive(&sample);
+            for len in 0..sample.len() {
+                receive(&sample[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_packets_dont_panic() {
+        let mut rng = StdRng::seed_from_u64(26);
+        let samples = samples();
+        for _ in 0..ROUNDS {
+            let mut bytes = samples[rng.gen_range(0..samples.len())].clone();
+            for _ in 0..rng.gen_range(1..8) {
+                let i = rng.gen_range(0..bytes.len());
+                bytes[i] = rng.gen();
+            }
+            receive(&bytes);
+        }
+    }
+
+    #[test]
+    fn random_addresses_dont_panic() {
+        let mut rng = StdRng::seed_from_u64(27);
+        let words = ["QTS", "_", "0", "256", "IN", "OUT", "PANIC", "PUSH", "HIT", "PROP", "SHOCK", "VIBRATE", "BEEP", "PATTERN", "40", "/", "é", ""];
+        for _ in 0..ROUNDS {
+            let address: String = (0..rng.gen_range(0..10)).map(|_| words[rng.gen_range(0..words.len())]).collect();
+            let _ = read_message(&OscMessage { addr: format!("/avatar/parameters/{}", address), args: vec![OscType::Float(rng.gen())] });
+            let _ = read_message(&OscMessage { addr: address, args: Vec::new() });
+        }
+    }
+}
@@ -19,20 +19,27 @@ function checkIpAddress(ip: string) {
     return ipv4Pattern.test(ip) || ipv6Pattern.test(ip); 
 } 
 
+function showError(err: unknown) {
+    console.log(err);
+    if (pageMsgEl) {
+        pageMsgEl.innerHTML = `${err}`;
+    }
+}
+
 async function shock() {
   if (shockStrengthEl) {
-    console.log(await invoke("shock", {shocker: 0, strength: shockStrengthEl.value }));
+    await invoke("shock", {target: "", strength: shockStrengthEl.value }).then(console.log, showError);
   }
 }
 
 async function vibrate() {
     if (vibrateStrengthEl) {
-      console.log(await invoke("vibrate", {shocker: 0, strength: vibrateStrengthEl.value }));
+      await invoke("vibrate", {target: "", strength: vibrateStrengthEl.value }).then(console.log, showError);
     }
 }
 
 async function beep() {
-    console.log(await invoke("beep", {shocker: 0}));
+    await invoke("beep", {target: ""}).then(console.log, showError);
 }
 
 window.addEventListener("DOMContentLoaded", async () => {
@@ -21,6 +21,11 @@ let qtshockIp: string;
 type Payload = {
     message: string;
   };
+
+type Settings = {
+    strengths: { shock: number, vibrate: number };
+    integrations: { vrc_enabled: boolean, cs_enabled: boolean };
+  };
   
 async function startCSEventListener() {
   await listen<Payload>('cs-rust-event', (event) => {
@@ -102,11 +107,18 @@ window.addEventListener("DOMContentLoaded", async () => {
     csToggle.addEventListener("change", async (e) => {
         if (e.target) {
             let toggled = (e.target as HTMLInputElement).checked;
-            await invoke("start_cs_listener", {start: toggled});
+            await invoke("start_cs_listener", {start: toggled}).catch(() => {
+                // The reason is already in the console
+                (e.target as HTMLInputElement).checked = false;
+            });
         }
     });
 
+    const settings: Settings = await invoke("get_settings");
+    csToggle.checked = settings.integrations.cs_enabled;
+
     shockStrengthEl = document.getElementById("shock-strength") as HTMLInputElement;
+    shockStrengthEl.value = `${settings.strengths.shock}`;
     shockStrengthEl.addEventListener("change", (e) => {
         if (e.target) {
             let strengthStr = (e.target as HTMLInputElement).value;
@@ -122,6 +134,7 @@ window.addEventListener("DOMContentLoaded", async () => {
         }
     });
     vibrateStrengthEl = document.getElementById("vibrate-strength") as HTMLInputElement;
+    vibrateStrengthEl.value = `${settings.strengths.vibrate}`;
     vibrateStrengthEl.addEventListener("change", (e) => {
         if (e.target) {
             let strengthStr = (e.target as HTMLInputElement).value;
@@ -134,4 +147,9 @@ window.addEventListener("DOMContentLoaded", async () => {
             setVibrateStrength(strength);
         }
     });
+    // Strengths can also change from the in-game radial menu
+    await listen<Settings>("settings-changed", (event) => {
+        if (shockStrengthEl) shockStrengthEl.value = `${event.payload.strengths.shock}`;
+        if (vibrateStrengthEl) vibrateStrengthEl.value = `${event.payload.strengths.vibrate}`;
+    });
 });
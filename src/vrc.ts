@@ -12,6 +12,11 @@ let oscConsole: HTMLElement | null;
 let shockStrengthEl: HTMLInputElement | null;
 let vibrateStrengthEl: HTMLInputElement | null;
 
+let oscListenEl: HTMLInputElement | null;
+let oscSendEl: HTMLInputElement | null;
+let oscQueryEl: HTMLInputElement | null;
+let oscConfig: OscConfig;
+
 let qtshockIp: string;
 
 
@@ -21,6 +26,22 @@ let qtshockIp: string;
 type Payload = {
     message: string;
   };
+
+type OscConfig = {
+    listen: string;
+    send: string;
+    local_send_port: number;
+    oscquery: boolean;
+    feedback: boolean;
+    allowed_sources: string[];
+    max_packets_per_second: number;
+  };
+
+type Settings = {
+    strengths: { shock: number, vibrate: number };
+    osc: OscConfig;
+    integrations: { vrc_enabled: boolean, cs_enabled: boolean };
+  };
   
   async function startOscEventListener() {
     await listen<Payload>('vrc-osc-event', (event) => {
@@ -47,6 +68,20 @@ async function setShockStrength(strength: number) {
     await invoke("set_shock_strength", {strength: strength })
 }
 
+async function setOscConfig() {
+    if (!oscListenEl || !oscSendEl || !oscQueryEl) {
+        return;
+    }
+    try {
+        oscConfig = await invoke("set_osc_config", {config: {...oscConfig, listen: oscListenEl.value.trim(), send: oscSendEl.value.trim(), oscquery: oscQueryEl.checked}});
+    } catch (e) {
+        await oscLog(`Couldn't change OSC addresses: ${e}`);
+    }
+    oscListenEl.value = oscConfig.listen;
+    oscSendEl.value = oscConfig.send;
+    oscQueryEl.checked = oscConfig.oscquery;
+}
+
 async function setVibrateStrength(strength: number) {
     if (!vibrateStrengthEl) {
         return;
@@ -93,11 +128,21 @@ window.addEventListener("DOMContentLoaded", async () => {
     startOscEventListener();
     
     vrcToggle.addEventListener("change", async (e) => {
-        invoke("start_vrc_osc", {start: (e.target as HTMLInputElement).checked})
-        await oscLog(`Toggled VRChat integration ${(e.target as HTMLInputElement).checked ? "ON" : "OFF"}`);
+        let toggle = e.target as HTMLInputElement;
+        try {
+            await invoke("start_vrc_osc", {start: toggle.checked});
+            await oscLog(`Toggled VRChat integration ${toggle.checked ? "ON" : "OFF"}`);
+        } catch {
+            // The reason is already in the console
+            toggle.checked = false;
+        }
     });
 
+    const settings: Settings = await invoke("get_settings");
+    vrcToggle.checked = settings.integrations.vrc_enabled;
+
     shockStrengthEl = document.getElementById("shock-strength") as HTMLInputElement;
+    shockStrengthEl.value = `${settings.strengths.shock}`;
     shockStrengthEl.addEventListener("change", (e) => {
         if (e.target) {
             let strengthStr = (e.target as HTMLInputElement).value;
@@ -113,6 +158,7 @@ window.addEventListener("DOMContentLoaded", async () => {
         }
     });
     vibrateStrengthEl = document.getElementById("vibrate-strength") as HTMLInputElement;
+    vibrateStrengthEl.value = `${settings.strengths.vibrate}`;
     vibrateStrengthEl.addEventListener("change", (e) => {
         if (e.target) {
             let strengthStr = (e.target as HTMLInputElement).value;
@@ -125,4 +171,20 @@ window.addEventListener("DOMContentLoaded", async () => {
             setVibrateStrength(strength);
         }
     });
+
+    oscConfig = settings.osc;
+    oscListenEl = document.getElementById("osc-listen") as HTMLInputElement;
+    oscSendEl = document.getElementById("osc-send") as HTMLInputElement;
+    oscQueryEl = document.getElementById("osc-query") as HTMLInputElement;
+    oscListenEl.value = oscConfig.listen;
+    oscSendEl.value = oscConfig.send;
+    oscQueryEl.checked = oscConfig.oscquery;
+    // Strengths can also change from the in-game radial menu
+    await listen<Settings>("settings-changed", (event) => {
+        if (shockStrengthEl) shockStrengthEl.value = `${event.payload.strengths.shock}`;
+        if (vibrateStrengthEl) vibrateStrengthEl.value = `${event.payload.strengths.vibrate}`;
+    });
+    oscListenEl.addEventListener("change", setOscConfig);
+    oscSendEl.addEventListener("change", setOscConfig);
+    oscQueryEl.addEventListener("change", setOscConfig);
 });